@@ -1,27 +1,88 @@
-use std::time::{SystemTime, UNIX_EPOCH};
-
+use crate::day;
 use crate::frame::AppFrame;
+use crate::markdown;
+use crate::quick_add;
 use crate::store::Store;
+use crate::types::{self, Priority};
+use crate::widgets::{self, EditAction};
+use crate::worklog::{SharedWorklog, Worklog};
 
-use super::store;
 use super::Task;
 
-use chrono::{DateTime, Local};
-use eframe::egui::{self, Align, Color32, Layout};
+use eframe::egui::{self, Align, Color32, Key, KeyboardShortcut, Layout, Modifiers};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// Open tasks due within this many days are listed first, under "Due soon".
+const DUE_SOON_DAYS: i64 = 3;
+
+/// How long the undo toast stays up.
+const TOAST_DISPLAY_TIME: Duration = Duration::from_secs(6);
+
+const UNDO_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Z);
+const REDO_SHORTCUT: KeyboardShortcut =
+    KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::Z);
+const MOVE_UP_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::ALT, Key::ArrowUp);
+const MOVE_DOWN_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::ALT, Key::ArrowDown);
+
+/// A short note at the bottom of the window, offering to undo what just happened.
+struct Toast {
+    text: String,
+    undoable: bool,
+    shown_at: Instant,
+}
 
 pub struct MyApp {
     new_task: String,
-    store: store::Store,
+    tag_filter: Option<String>,
+    priority_view: widgets::PriorityView,
+    /// The task being edited in place and its description buffer.
+    editing: Option<(String, String)>,
+    /// The task clicked last, moved with Alt+Up/Down.
+    selected: Option<String>,
+    /// The task whose details window is open.
+    details: Option<String>,
+    /// The task whose notes are being edited, and the draft.
+    notes: Option<(String, String)>,
+    /// The step typed into the details window, to be added as a subtask.
+    new_subtask: String,
+    /// Tasks whose subtasks are hidden.
+    collapsed: HashSet<String>,
+    worklog: SharedWorklog,
     app_frame: AppFrame,
+    toast: Option<Toast>,
 }
 
 impl Default for MyApp {
     fn default() -> Self {
-        let store = Store::default().unwrap();
+        // Still come up when the database cannot be opened, so the error can
+        // be shown instead of the app silently failing to start.
+        let opened = Store::default().and_then(|store| {
+            day::set_day_start_hour(store.day_start_hour()?);
+            Worklog::shared(store)
+        });
+        let worklog = match opened {
+            Ok(worklog) => worklog,
+            Err(err) => {
+                let store = Store::in_memory().expect("failed to create in-memory database");
+                let worklog = Worklog::shared(store).expect("failed to load in-memory database");
+                worklog.lock().unwrap().report_error(err);
+                worklog
+            }
+        };
         Self {
             new_task: "".to_owned(),
-            store,
-            app_frame: AppFrame::default(),
+            tag_filter: None,
+            priority_view: widgets::PriorityView::default(),
+            editing: None,
+            selected: None,
+            details: None,
+            notes: None,
+            new_subtask: String::new(),
+            collapsed: HashSet::new(),
+            app_frame: AppFrame::new(worklog.clone()),
+            worklog,
+            toast: None,
         }
     }
 }
@@ -32,9 +93,79 @@ impl eframe::App for MyApp {
     }
 
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        let now: DateTime<Local> = Local::now();
-        let date_string = now.format("%Y-%m-%d").to_string();
+        let date_string = day::today().format("%Y-%m-%d").to_string();
         let title = format!("{} {}", "🔆", date_string);
+        let current_project = self.app_frame.current_project();
+
+        // text fields have their own undo
+        if !ctx.wants_keyboard_input() {
+            // check redo first, undo's shortcut would match it too
+            if ctx.input_mut(|i| i.consume_shortcut(&REDO_SHORTCUT)) {
+                self.undo_redo(false);
+            } else if ctx.input_mut(|i| i.consume_shortcut(&UNDO_SHORTCUT)) {
+                self.undo_redo(true);
+            }
+        }
+
+        // pick up writes made outside this process, e.g. by the cli
+        ctx.request_repaint_after(std::time::Duration::from_secs(1));
+        let (tasks, projects, time_entries, running_task, render_markdown) = {
+            let mut worklog = self.worklog.lock().unwrap();
+            let _ = worklog.refresh_if_changed();
+            let running_task = worklog.running_timer().map(|e| e.task_id.clone());
+            (
+                worklog.tasks(),
+                worklog.projects(),
+                worklog.time_entries(),
+                running_task,
+                worklog.render_markdown(),
+            )
+        };
+        let subtask_progress = types::subtask_progress(&tasks);
+        let tracked_by_task = types::tracked_seconds(&time_entries);
+
+        let tag_filter = self.tag_filter.clone();
+        let priority_view = self.priority_view;
+        let mut visible: Vec<&Task> = tasks
+            .iter()
+            .filter(|todo| todo.is_today() || !todo.completed)
+            .filter(|todo| tag_filter.as_ref().map_or(true, |tag| todo.has_tag(tag)))
+            .filter(|todo| current_project.is_none() || todo.project_id == current_project)
+            .filter(|todo| priority_view.matches(todo))
+            .collect();
+        priority_view.sort(&mut visible);
+        // subtasks are listed under their parent when it is listed
+        let listed: HashSet<&str> = visible.iter().map(|task| task.id.as_str()).collect();
+        visible.retain(|task| {
+            !task
+                .parent_id
+                .as_deref()
+                .is_some_and(|id| listed.contains(id))
+        });
+        // tasks due soon go first, soonest first, and keep out of the manual order
+        let (mut due_soon, visible): (Vec<&Task>, Vec<&Task>) = visible
+            .into_iter()
+            .partition(|task| task.is_due_within(DUE_SOON_DAYS));
+        due_soon.sort_by_key(|task| task.due_at);
+        let visible_ids: Vec<String> = visible.iter().map(|task| task.id.clone()).collect();
+        // the manual order is hidden while sorting by priority
+        let reorderable = !priority_view.sort;
+
+        // move the selected task up or down the list
+        let selected_index = self
+            .selected
+            .as_ref()
+            .and_then(|id| visible_ids.iter().position(|v| v == id));
+        if let (Some(index), false, true) =
+            (selected_index, ctx.wants_keyboard_input(), reorderable)
+        {
+            if ctx.input_mut(|i| i.consume_shortcut(&MOVE_UP_SHORTCUT)) && index > 0 {
+                self.move_task(visible_ids.clone(), index, index - 1);
+            } else if ctx.input_mut(|i| i.consume_shortcut(&MOVE_DOWN_SHORTCUT)) {
+                self.move_task(visible_ids.clone(), index, index + 1);
+            }
+        }
+        let mut dropped: Option<(usize, usize)> = None;
 
         self.app_frame.window(ctx, title.as_str(), |ui| {
             egui::CentralPanel::default().show_inside(ui, |ui| {
@@ -48,72 +179,554 @@ impl eframe::App for MyApp {
                 ui.add_space(12.0);
 
                 // input area
+                let mut new_task_focused = false;
                 ui.horizontal(|ui| {
                     ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
                         ui.style_mut().visuals.extreme_bg_color =
                             faded_color(Color32::from_white_alpha(9));
-                        ui.add(
+                        let input = ui.add(
                             egui::TextEdit::multiline(&mut self.new_task)
                                 .frame(true)
-                                .hint_text("add new task by press Enter")
+                                .hint_text(
+                                    "add new task by press Enter, use #tag, @project, !1-!4 and dates like tomorrow 3pm",
+                                )
                                 .desired_width(f32::INFINITY),
                         );
+                        new_task_focused = input.has_focus();
                     });
 
-                    if ui.input(|input| input.key_pressed(egui::Key::Enter)) {
-                        let task = Task::new(self.new_task.clone());
+                    if new_task_focused && ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+                        let parsed = quick_add::parse(&self.new_task);
                         self.new_task.clear(); // Reset input field
 
-                        // save in the store
-                        self.store.add_task(&task).unwrap();
+                        if !parsed.description.is_empty() {
+                            let mut task = Task::new(parsed.description);
+                            task.tags = parsed.tags;
+                            task.priority = parsed.priority;
+                            task.due_at = parsed.due_at;
+                            let mut worklog = self.worklog.lock().unwrap();
+                            task.project_id = match parsed.project {
+                                Some(name) => {
+                                    let project = worklog.find_or_create_project(&name);
+                                    worklog.report(project).map(|project| project.id)
+                                }
+                                None => current_project.clone(),
+                            };
+
+                            // save in the store
+                            let result = worklog.add_task(task);
+                            worklog.report(result);
+                        }
                     }
                 });
 
+                // show the due date the input will get before it is added
+                let preview = quick_add::parse(&self.new_task);
+                if let (Some(due_at), Some(phrase)) = (preview.due_at, &preview.due_phrase) {
+                    ui.add_space(4.0);
+                    widgets::due_preview(ui, phrase, due_at);
+                }
+
                 ui.add_space(12.0);
 
+                ui.horizontal(|ui| {
+                    widgets::priority_menu(ui, &mut self.priority_view);
+                    widgets::tag_filter_bar(ui, &mut self.tag_filter);
+                });
+
                 // Display todo list
                 egui::ScrollArea::vertical().show(ui, |ui| {
                     // Add a lot of widgets here.
 
-                    let mut tasks = self.store.get_all_tasks().unwrap_or_default();
-                    for task in tasks
-                        .iter_mut()
-                        .filter(|todo| todo.is_today() || !todo.completed)
-                    {
-                        ui.horizontal(|ui| {
+                    if !due_soon.is_empty() {
+                        ui.label(egui::RichText::new("Due soon").small().weak());
+                    }
+                    let rows = due_soon
+                        .iter()
+                        .map(|task| (None, *task))
+                        .chain(visible.iter().enumerate().map(|(i, task)| (Some(i), *task)))
+                        .flat_map(|(index, task)| {
+                            let expanded = !self.collapsed.contains(&task.id);
+                            let subtasks = tasks
+                                .iter()
+                                .filter(move |t| expanded && t.parent_id.as_ref() == Some(&task.id))
+                                .map(|subtask| (None, subtask, true));
+                            std::iter::once((index, task, false)).chain(subtasks)
+                        })
+                        .collect::<Vec<_>>();
+                    for (index, task, nested) in rows {
+                        if index == Some(0) && !due_soon.is_empty() {
+                            ui.separator();
+                        }
+                        let mut task = task.clone();
+                        let task = &mut task;
+                        let row = ui.horizontal(|ui| {
                             let description = task.description.clone();
                             let mut completed = task.completed;
 
                             ui.columns(1, |cols| {
                                 cols[0].horizontal_centered(|ui| {
+                                    if nested {
+                                        ui.add_space(20.0);
+                                    }
+                                    // only open tasks are reordered, the handle
+                                    // keeps its space on completed ones
+                                    let handle = egui::RichText::new("☰").weak();
+                                    let index = index.filter(|_| reorderable && !task.completed);
+                                    if let Some(index) = index {
+                                        let drag_id = egui::Id::new(("drag_task", &task.id));
+                                        ui.dnd_drag_source(drag_id, index, |ui| ui.label(handle))
+                                            .response
+                                            .on_hover_text(
+                                                "Drag to reorder, or select and use Alt+Up/Down",
+                                            );
+                                    } else {
+                                        ui.label(handle.color(Color32::TRANSPARENT));
+                                    }
+                                    let progress = subtask_progress.get(task.id.as_str()).copied();
+                                    if progress.is_some() {
+                                        let expanded = !self.collapsed.contains(&task.id);
+                                        let (icon, hover) = if expanded {
+                                            ("⏷", "Hide subtasks")
+                                        } else {
+                                            ("⏵", "Show subtasks")
+                                        };
+                                        let toggle = egui::Button::new(icon).small().frame(false);
+                                        if ui.add(toggle).on_hover_text(hover).clicked() {
+                                            if expanded {
+                                                self.collapsed.insert(task.id.clone());
+                                            } else {
+                                                self.collapsed.remove(&task.id);
+                                            }
+                                        }
+                                    }
+                                    if widgets::priority_marker(ui, &mut task.priority) {
+                                        let mut worklog = self.worklog.lock().unwrap();
+                                        let result = worklog.update_task(task);
+                                        worklog.report(result);
+                                    }
                                     if ui.checkbox(&mut completed, "").clicked() {
-                                        task.completed = completed;
-
-                                        // update the completed_at
-                                        if completed {
-                                            let now = SystemTime::now();
-                                            let completed_at = now
-                                                .duration_since(UNIX_EPOCH)
-                                                .expect("Time went backwards")
-                                                .as_secs();
-                                            task.completed_at = completed_at;
+                                        task.set_completed(completed);
+                                        let mut worklog = self.worklog.lock().unwrap();
+                                        let result = worklog.update_task(task);
+                                        worklog.report(result);
+                                    }
+                                    let running = running_task.as_ref() == Some(&task.id);
+                                    if widgets::timer_button(ui, running).clicked() {
+                                        let mut worklog = self.worklog.lock().unwrap();
+                                        let result = if running {
+                                            worklog.stop_timer()
                                         } else {
-                                            task.completed_at = 0;
+                                            worklog.start_timer(&task.id)
+                                        };
+                                        worklog.report(result);
+                                    }
+                                    let editing =
+                                        self.editing.as_mut().filter(|(id, _)| id == &task.id);
+                                    if let Some((_, buffer)) = editing {
+                                        match widgets::inline_editor(ui, buffer) {
+                                            EditAction::Commit => {
+                                                let description = buffer.trim().to_owned();
+                                                if !description.is_empty() {
+                                                    task.description = description;
+                                                    let mut worklog = self.worklog.lock().unwrap();
+                                                    let result = worklog.update_task(task);
+                                                    worklog.report(result);
+                                                }
+                                                self.editing = None;
+                                            }
+                                            EditAction::Cancel => self.editing = None,
+                                            EditAction::Continue => {}
                                         }
+                                        return;
+                                    }
 
-                                        self.store.update_task(task).unwrap();
+                                    let selected = self.selected.as_ref() == Some(&task.id);
+                                    let background = selected
+                                        .then_some(ui.visuals().selection.bg_fill);
+                                    let label = widgets::description_label(
+                                        ui,
+                                        description.trim_end(),
+                                        background,
+                                        !render_markdown,
+                                    );
+                                    if label.clicked() {
+                                        self.selected = if selected {
+                                            None
+                                        } else {
+                                            Some(task.id.clone())
+                                        };
+                                    }
+                                    if label.double_clicked() {
+                                        self.editing = Some((
+                                            task.id.clone(),
+                                            description.trim_end().to_owned(),
+                                        ));
+                                    }
+                                    // the project is implied when the list is limited to one
+                                    if current_project.is_none() {
+                                        if let Some(project) = task
+                                            .project_id
+                                            .as_ref()
+                                            .and_then(|id| projects.iter().find(|p| &p.id == id))
+                                        {
+                                            widgets::project_label(ui, project);
+                                        }
+                                    }
+                                    for tag in task.tags.iter() {
+                                        let selected = self.tag_filter.as_ref() == Some(tag);
+                                        if widgets::tag_chip(ui, tag, selected).clicked() {
+                                            widgets::toggle_tag_filter(&mut self.tag_filter, tag);
+                                        }
+                                    }
+                                    if let Some((done, total)) = progress {
+                                        widgets::subtask_progress(ui, done, total);
+                                    }
+                                    if widgets::due_badge(ui, task).is_some_and(|b| b.clicked()) {
+                                        self.details = Some(task.id.clone());
+                                    }
+                                    if !task.notes.trim().is_empty() {
+                                        let notes = ui
+                                            .add(egui::Button::new("📝").small().frame(false))
+                                            .on_hover_ui(|ui| markdown::show(ui, &task.notes));
+                                        if notes.clicked() {
+                                            self.details = Some(task.id.clone());
+                                        }
+                                    }
+                                    if let Some(recurrence) = &task.recurrence {
+                                        ui.label(egui::RichText::new("🔁").small().weak())
+                                            .on_hover_text(recurrence.describe());
+                                    }
+                                    let tracked = tracked_by_task
+                                        .get(task.id.as_str())
+                                        .copied()
+                                        .unwrap_or_default();
+                                    if tracked > 0 {
+                                        ui.label(
+                                            egui::RichText::new(types::format_duration(
+                                                tracked, running,
+                                            ))
+                                            .weak(),
+                                        );
                                     }
-                                    ui.label(description.trim_end());
 
-                                    if ui.add(egui::Button::new("❌").small()).clicked() {
-                                        self.store.delete_task_by_id(&task.id).unwrap();
+                                    if ui
+                                        .add(egui::Button::new("📅").small().frame(false))
+                                        .on_hover_text("Details, notes, due date and repeat")
+                                        .clicked()
+                                    {
+                                        self.details = Some(task.id.clone());
+                                    }
+                                    if ui
+                                        .add(egui::Button::new("❌").small())
+                                        .on_hover_text("Move to trash")
+                                        .clicked()
+                                    {
+                                        let mut worklog = self.worklog.lock().unwrap();
+                                        let result = worklog.delete_task(&task.id);
+                                        worklog.report(result);
                                     }
                                 });
                             });
                         });
+
+                        // show where a dragged task would go, and put it there
+                        let Some(index) = index else {
+                            continue;
+                        };
+                        if let Some(from) = row.response.dnd_hover_payload::<usize>() {
+                            if *from != index {
+                                let rect = row.response.rect;
+                                let y = if *from < index {
+                                    rect.bottom()
+                                } else {
+                                    rect.top()
+                                };
+                                ui.painter().hline(
+                                    rect.x_range(),
+                                    y,
+                                    ui.visuals().selection.stroke,
+                                );
+                            }
+                        }
+                        if let Some(from) = row.response.dnd_release_payload::<usize>() {
+                            dropped = Some((*from, index));
+                        }
                     }
                 });
             });
         });
+
+        if let Some((from, to)) = dropped {
+            self.move_task(visible_ids, from, to);
+        }
+
+        if let Some(command) = self.worklog.lock().unwrap().take_last_command() {
+            if command.is_destructive() {
+                self.toast = Some(Toast {
+                    text: command.describe(),
+                    undoable: true,
+                    shown_at: Instant::now(),
+                });
+            }
+        }
+        self.details_ui(ctx, &tasks);
+        self.toast_ui(ctx);
+    }
+}
+
+impl MyApp {
+    /// Move the task at `from` in the visible list to `to`.
+    fn move_task(&mut self, mut ids: Vec<String>, from: usize, to: usize) {
+        if from == to || from >= ids.len() || to >= ids.len() {
+            return;
+        }
+        let id = ids.remove(from);
+        ids.insert(to, id);
+        let mut worklog = self.worklog.lock().unwrap();
+        let result = worklog.reorder(&ids);
+        worklog.report(result);
+    }
+
+    /// A small window to set a task's priority, due date, notes and subtasks.
+    fn details_ui(&mut self, ctx: &egui::Context, tasks: &[Task]) {
+        // keep a draft for another task when the window switches to this one
+        if let Some((id, draft)) = self.notes.take() {
+            if self.details.as_ref() == Some(&id) {
+                self.notes = Some((id, draft));
+            } else {
+                self.save_notes(tasks, &id, draft);
+            }
+        }
+
+        let Some(task) = self
+            .details
+            .as_ref()
+            .and_then(|id| tasks.iter().find(|t| &t.id == id))
+        else {
+            self.details = None;
+            return;
+        };
+
+        let mut open = true;
+        let mut changed = task.clone();
+        let mut subtask_updates = Vec::new();
+        let mut new_subtask = None;
+        egui::Window::new("Task details")
+            .id(egui::Id::new("task_details"))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .default_width(320.0)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(egui::RichText::new(task.description.trim_end()).strong());
+                ui.separator();
+                egui::Grid::new("task_details_grid")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Priority");
+                        egui::ComboBox::from_id_source("task_details_priority")
+                            .selected_text(changed.priority.label())
+                            .show_ui(ui, |ui| {
+                                for priority in Priority::ALL {
+                                    ui.selectable_value(
+                                        &mut changed.priority,
+                                        priority,
+                                        priority.label(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Due");
+                        ui.horizontal(|ui| match (task.due_at, task.due_day()) {
+                            (Some(due_at), Some(due_day)) => {
+                                let mut date = due_day;
+                                ui.add(
+                                    egui_extras::DatePickerButton::new(&mut date)
+                                        .id_source("task_details_due"),
+                                );
+                                if date != due_day {
+                                    // keep the time of day, if there is one
+                                    let time = due_at.saturating_sub(day::start_of(due_day));
+                                    changed.due_at = Some(day::start_of(date) + time);
+                                }
+                                if day::has_time(due_at) {
+                                    ui.label(day::time_of(due_at));
+                                }
+                                if ui.small_button("✖").on_hover_text("No due date").clicked() {
+                                    changed.due_at = None;
+                                }
+                            }
+                            _ => {
+                                if ui.button("Set due date").clicked() {
+                                    changed.due_at = Some(day::start_of(day::today()));
+                                }
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label("Repeat");
+                        if widgets::recurrence_editor(ui, &mut changed.recurrence)
+                            && changed.due_at.is_none()
+                        {
+                            // occurrences are told apart by their due dates
+                            let first = changed
+                                .recurrence
+                                .as_ref()
+                                .and_then(|r| r.first_due(day::today()));
+                            changed.due_at = first.map(day::start_of);
+                        }
+                        ui.end_row();
+                    });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Notes");
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        if let Some((_, draft)) = self.notes.take() {
+                            if ui.small_button("Done").clicked() {
+                                changed.notes = draft.trim_end().to_owned();
+                            } else {
+                                self.notes = Some((task.id.clone(), draft));
+                            }
+                        } else if ui.small_button("Edit").clicked() {
+                            self.notes = Some((task.id.clone(), task.notes.clone()));
+                        }
+                    });
+                });
+                if let Some((_, draft)) = self.notes.as_mut() {
+                    ui.add(
+                        egui::TextEdit::multiline(draft)
+                            .desired_rows(6)
+                            .desired_width(f32::INFINITY)
+                            .hint_text("**bold**, *italic*, `code`, - lists, [links](https://…)"),
+                    );
+                } else if task.notes.trim().is_empty() {
+                    ui.label(egui::RichText::new("No notes").weak());
+                } else {
+                    egui::ScrollArea::vertical()
+                        .max_height(240.0)
+                        .show(ui, |ui| markdown::show(ui, &task.notes));
+                }
+
+                // subtasks are one level deep
+                if task.parent_id.is_some() {
+                    return;
+                }
+                ui.separator();
+                ui.label("Subtasks");
+                for subtask in tasks
+                    .iter()
+                    .filter(|t| t.parent_id.as_ref() == Some(&task.id))
+                {
+                    let mut completed = subtask.completed;
+                    if ui
+                        .checkbox(&mut completed, subtask.description.trim_end())
+                        .clicked()
+                    {
+                        let mut subtask = subtask.clone();
+                        subtask.set_completed(completed);
+                        subtask_updates.push(subtask);
+                    }
+                }
+                let input =
+                    ui.add(egui::TextEdit::singleline(&mut self.new_subtask).hint_text("new step"));
+                if input.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+                    let description = self.new_subtask.trim().to_owned();
+                    if !description.is_empty() {
+                        new_subtask = Some(description);
+                    }
+                    self.new_subtask.clear();
+                    input.request_focus();
+                }
+            });
+
+        let mut worklog = self.worklog.lock().unwrap();
+        if changed != *task {
+            let result = worklog.update_task(&changed);
+            worklog.report(result);
+        }
+        for subtask in subtask_updates {
+            let result = worklog.update_task(&subtask);
+            worklog.report(result);
+        }
+        if let Some(description) = new_subtask {
+            let result = worklog.add_subtask(task, description);
+            worklog.report(result);
+        }
+        if !open {
+            if let Some((id, draft)) = self.notes.take() {
+                drop(worklog);
+                self.save_notes(tasks, &id, draft);
+            }
+            self.details = None;
+        }
+    }
+
+    /// Save a notes draft that was left without pressing Done.
+    fn save_notes(&self, tasks: &[Task], id: &str, draft: String) {
+        let Some(task) = tasks.iter().find(|t| t.id == id) else {
+            return;
+        };
+        let notes = draft.trim_end();
+        if notes != task.notes {
+            let mut task = task.clone();
+            task.notes = notes.to_owned();
+            let mut worklog = self.worklog.lock().unwrap();
+            let result = worklog.update_task(&task);
+            worklog.report(result);
+        }
+    }
+
+    fn undo_redo(&mut self, undo: bool) {
+        let mut worklog = self.worklog.lock().unwrap();
+        let result = if undo { worklog.undo() } else { worklog.redo() };
+        let text = match worklog.report(result) {
+            Some(Some(command)) if undo => format!("Undone: {}", command.describe()),
+            Some(Some(command)) => format!("Redone: {}", command.describe()),
+            Some(None) if undo => "Nothing to undo".to_owned(),
+            Some(None) => "Nothing to redo".to_owned(),
+            None => return,
+        };
+        self.editing = None;
+        self.toast = Some(Toast {
+            text,
+            undoable: false,
+            shown_at: Instant::now(),
+        });
+    }
+
+    fn toast_ui(&mut self, ctx: &egui::Context) {
+        let Some(toast) = &self.toast else {
+            return;
+        };
+        let elapsed = toast.shown_at.elapsed();
+        if elapsed > TOAST_DISPLAY_TIME {
+            self.toast = None;
+            return;
+        }
+        ctx.request_repaint_after(TOAST_DISPLAY_TIME - elapsed);
+
+        let mut undo = false;
+        egui::Area::new(egui::Id::new("undo_toast"))
+            .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -16.0])
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(&toast.text);
+                        if toast.undoable {
+                            undo = ui
+                                .button("Undo")
+                                .on_hover_text("Ctrl+Z, Ctrl+Shift+Z to redo")
+                                .clicked();
+                        }
+                    });
+                });
+            });
+        if undo {
+            self.undo_redo(true);
+        }
     }
 }
@@ -0,0 +1,565 @@
+use crate::command::{Command, CommandKind};
+use crate::config;
+use crate::day;
+use crate::export::{self, DateRange};
+use crate::import;
+use crate::quick_add;
+use crate::recurrence;
+use crate::store::Store;
+use crate::timesheet::{GroupBy, Timesheet};
+use crate::types::{self, Project, Task};
+use crate::worklog;
+use chrono::{Duration, NaiveDate};
+use serde::Serialize;
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: small-worklog [COMMAND] [OPTIONS]
+
+Without a command the desktop app is started.
+
+Global options:
+  --db <FILE>            Use FILE as the database, overriding SMALL_WORKLOG_DB
+                         and the config file
+
+Commands:
+  add <description...> [--parent <id-prefix>]
+                         Add a task, #tag, @project, !1-!4 priority tokens and due
+                         dates like `tomorrow 3pm` work like in the app, or a
+                         subtask of the given task
+  list [--date <DATE>]   List today's tasks and open tasks, or the tasks of DATE
+  done <id-prefix>       Mark a task as completed
+  delete <id-prefix>     Move a task and its subtasks to the trash
+  undo                   Revert the last add, edit, completion or delete, made
+                         here or in the app
+  redo                   Apply the last undone change again
+  trash                  List the tasks in the trash
+  restore <id-prefix>    Bring a task back from the trash
+  report [--week | --last-week | --date <DATE>]
+                         Show the tasks of today, of a week or of DATE
+  timesheet [--week | --last-week | --date <DATE>] [--by-tag] [--html]
+                         Tracked time per day and project (or tag) with the
+                         hourly rates from the config file, as CSV or HTML
+  export [--csv]         Back up every task as versioned JSON, or as CSV
+  import <FILE>          Merge a JSON or CSV backup, skipping tasks that already exist
+  help                   Show this message
+
+Options:
+  --json                 Print machine readable JSON instead of text
+  --markdown             Render `report` as Markdown
+  --notes                Add each task's notes to `report`
+  --csv                  Write `export` as CSV
+  --html                 Render `timesheet` as a printable HTML page
+  --by-tag               Group `timesheet` by each task's first tag
+  --out <FILE>           Write `report`, `timesheet` or `export` to FILE
+                         instead of stdout
+  --dry-run              Show what `import` would do without changing anything
+
+DATE is YYYY-MM-DD, `today` or `yesterday`.";
+
+/// Options shared by all subcommands.
+struct Options {
+    json: bool,
+    markdown: bool,
+    csv: bool,
+    html: bool,
+    by_tag: bool,
+    dry_run: bool,
+    notes: bool,
+    date: Option<NaiveDate>,
+    week: bool,
+    last_week: bool,
+    out: Option<String>,
+    parent: Option<String>,
+    rest: Vec<String>,
+}
+
+/// Strip the global `--db <FILE>` option from the arguments, applying it for
+/// both the CLI and the app.
+pub fn take_database_option(args: Vec<String>) -> Result<Vec<String>, String> {
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--db" {
+            let path = args.next().ok_or("--db needs a value")?;
+            config::set_database_override(PathBuf::from(path));
+        } else if let Some(path) = arg.strip_prefix("--db=") {
+            config::set_database_override(PathBuf::from(path));
+        } else {
+            rest.push(arg);
+        }
+    }
+    Ok(rest)
+}
+
+/// Run a subcommand against the same database the app uses, returning the
+/// process exit code. Returns `None` when no subcommand was given and the GUI
+/// should start instead.
+pub fn run(args: &[String]) -> Option<i32> {
+    let (command, args) = args.split_first()?;
+    let result = parse_options(args).and_then(|options| match command.as_str() {
+        "add" => add(&options),
+        "list" => list(&options),
+        "done" => done(&options),
+        "delete" => delete(&options),
+        "undo" => undo_redo(&options, true),
+        "redo" => undo_redo(&options, false),
+        "trash" => trash(&options),
+        "restore" => restore(&options),
+        "report" => report(&options),
+        "timesheet" => timesheet(&options),
+        "export" => export(&options),
+        "import" => import(&options),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("unknown command `{}`\n\n{}", command, USAGE)),
+    });
+
+    match result {
+        Ok(()) => Some(0),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            Some(1)
+        }
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        json: false,
+        markdown: false,
+        csv: false,
+        html: false,
+        by_tag: false,
+        dry_run: false,
+        notes: false,
+        date: None,
+        week: false,
+        last_week: false,
+        out: None,
+        parent: None,
+        rest: Vec::new(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => options.json = true,
+            "--markdown" => options.markdown = true,
+            "--csv" => options.csv = true,
+            "--html" => options.html = true,
+            "--by-tag" => options.by_tag = true,
+            "--dry-run" => options.dry_run = true,
+            "--notes" => options.notes = true,
+            "--week" => options.week = true,
+            "--last-week" => options.last_week = true,
+            "--out" => {
+                let value = args.next().ok_or("--out needs a value")?;
+                options.out = Some(value.clone());
+            }
+            "--parent" => {
+                let value = args.next().ok_or("--parent needs a value")?;
+                options.parent = Some(value.clone());
+            }
+            "--date" => {
+                let value = args.next().ok_or("--date needs a value")?;
+                options.date = Some(parse_date(value)?);
+            }
+            _ => options.rest.push(arg.clone()),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    let today = day::today();
+    match value {
+        "today" => Ok(today),
+        "yesterday" => Ok(today - Duration::days(1)),
+        _ => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map_err(|_| format!("invalid date `{}`, expected YYYY-MM-DD", value)),
+    }
+}
+
+fn open_store() -> Result<Store, String> {
+    Store::default()
+        .and_then(|store| {
+            day::set_day_start_hour(store.day_start_hour()?);
+            Ok(store)
+        })
+        .map_err(|err| format!("failed to open database: {}", err))
+}
+
+fn add(options: &Options) -> Result<(), String> {
+    let parsed = quick_add::parse(&options.rest.join(" "));
+    if parsed.description.is_empty() {
+        return Err("the task description is empty".to_owned());
+    }
+
+    let store = open_store()?;
+    let mut task = Task::new(parsed.description);
+    task.tags = parsed.tags;
+    task.priority = parsed.priority;
+    task.due_at = parsed.due_at;
+    if let Some(name) = parsed.project {
+        let project = store
+            .find_or_create_project(&name)
+            .map_err(|err| err.to_string())?;
+        task.project_id = Some(project.id);
+    }
+    if let Some(prefix) = &options.parent {
+        let tasks = store.get_all_tasks().map_err(|err| err.to_string())?;
+        let parent = match_prefix(tasks, prefix)?;
+        if parent.parent_id.is_some() {
+            return Err("subtasks cannot have subtasks of their own".to_owned());
+        }
+        task.project_id = task.project_id.or(parent.project_id);
+        task.parent_id = Some(parent.id);
+    }
+    task.position = store.next_position().map_err(|err| err.to_string())?;
+    store
+        .execute(&Command::add(task.clone()))
+        .map_err(|err| err.to_string())?;
+
+    if options.json {
+        print_json(&task)
+    } else {
+        println!("added {}", short_id(&task.id));
+        Ok(())
+    }
+}
+
+fn list(options: &Options) -> Result<(), String> {
+    let store = open_store()?;
+    let tasks = store.get_all_tasks().map_err(|err| err.to_string())?;
+    let tasks: Vec<&Task> = match options.date {
+        Some(date) => {
+            let date = date.to_string();
+            tasks
+                .iter()
+                .filter(|task| task.created_at_date() == date)
+                .collect()
+        }
+        // the same selection as the main window
+        None => tasks
+            .iter()
+            .filter(|task| task.is_today() || !task.completed)
+            .collect(),
+    };
+
+    if options.json {
+        return print_json(&tasks);
+    }
+    let projects = store.get_all_projects().map_err(|err| err.to_string())?;
+    for (task, nested) in types::nest(&tasks) {
+        let indent = if nested { "  " } else { "" };
+        println!("{}{}", indent, format_task(task, &projects));
+    }
+    Ok(())
+}
+
+fn done(options: &Options) -> Result<(), String> {
+    let store = open_store()?;
+    let tasks = store.get_all_tasks().map_err(|err| err.to_string())?;
+    let before = find_by_prefix(tasks.clone(), options)?;
+    let mut task = before.clone();
+    task.set_completed(true);
+    let position = store.next_position().map_err(|err| err.to_string())?;
+    let parent = match store.auto_complete_parent() {
+        Ok(true) => worklog::parent_update(&tasks, &before, &task),
+        _ => None,
+    };
+    let mut commands = recurrence::update(before, task.clone(), position);
+    if let Some((before, after)) = parent.clone() {
+        commands.extend(recurrence::update(before, after, position + 1));
+    }
+    store
+        .execute_all(&commands)
+        .map_err(|err| err.to_string())?;
+
+    if options.json {
+        return print_json(&task);
+    }
+    println!("completed {}", short_id(&task.id));
+    let added = commands
+        .iter()
+        .filter(|command| command.kind == CommandKind::Add);
+    if let Some(next) = added.filter_map(|command| command.after.as_ref()).next() {
+        println!("next occurrence {}", short_id(&next.id));
+    }
+    if let Some((_, parent)) = parent {
+        println!(
+            "completed {}, all its subtasks are done",
+            short_id(&parent.id)
+        );
+    }
+    Ok(())
+}
+
+fn delete(options: &Options) -> Result<(), String> {
+    let store = open_store()?;
+    let tasks = store.get_all_tasks().map_err(|err| err.to_string())?;
+    let task = find_by_prefix(tasks.clone(), options)?;
+    store
+        .execute_all(&worklog::delete_commands(&tasks, task.clone()))
+        .map_err(|err| err.to_string())?;
+
+    if options.json {
+        print_json(&task)
+    } else {
+        println!("moved {} to the trash", short_id(&task.id));
+        Ok(())
+    }
+}
+
+fn undo_redo(options: &Options, undo: bool) -> Result<(), String> {
+    let store = open_store()?;
+    let command = if undo { store.undo() } else { store.redo() };
+    let command = command.map_err(|err| err.to_string())?;
+
+    if options.json {
+        return print_json(&command.as_ref().map(|command| command.describe()));
+    }
+    match (command, undo) {
+        (Some(command), true) => println!("undone: {}", command.describe()),
+        (Some(command), false) => println!("redone: {}", command.describe()),
+        (None, true) => println!("nothing to undo"),
+        (None, false) => println!("nothing to redo"),
+    }
+    Ok(())
+}
+
+fn trash(options: &Options) -> Result<(), String> {
+    let store = open_store()?;
+    let tasks = store.get_trashed_tasks().map_err(|err| err.to_string())?;
+
+    if options.json {
+        return print_json(&tasks);
+    }
+    let projects = store.get_all_projects().map_err(|err| err.to_string())?;
+    for task in tasks {
+        let deleted_on = task.deleted_at.map(day::day_of).unwrap_or_default();
+        println!("{}  deleted {}", format_task(&task, &projects), deleted_on);
+    }
+    Ok(())
+}
+
+fn restore(options: &Options) -> Result<(), String> {
+    let store = open_store()?;
+    let trashed = store.get_trashed_tasks().map_err(|err| err.to_string())?;
+    let mut task = find_by_prefix(trashed, options)?;
+    store
+        .restore_task(&task.id)
+        .map_err(|err| err.to_string())?;
+    task.deleted_at = None;
+
+    if options.json {
+        print_json(&task)
+    } else {
+        println!("restored {}", short_id(&task.id));
+        Ok(())
+    }
+}
+
+/// The days picked by `--week`, `--last-week` or `--date`, today by default.
+fn date_range(options: &Options) -> DateRange {
+    if options.week {
+        DateRange::this_week()
+    } else if options.last_week {
+        DateRange::last_week()
+    } else {
+        options
+            .date
+            .map(DateRange::day)
+            .unwrap_or_else(DateRange::today)
+    }
+}
+
+fn report(options: &Options) -> Result<(), String> {
+    let range = date_range(options);
+
+    let store = open_store()?;
+    let tasks = store.get_all_tasks().map_err(|err| err.to_string())?;
+    let projects = store.get_all_projects().map_err(|err| err.to_string())?;
+
+    let tasks_by_date = export::tasks_by_date(
+        tasks
+            .iter()
+            .filter(|task| range.contains(&task.created_at_date())),
+    );
+
+    let output = if options.json {
+        serde_json::to_string_pretty(&tasks_by_date).map_err(|err| err.to_string())? + "\n"
+    } else if options.markdown {
+        export::markdown_report(&tasks, &projects, range, options.notes)
+    } else {
+        let mut output = String::new();
+        for (date, tasks) in tasks_by_date.iter() {
+            output.push_str(&format!("{}\n", date));
+            for task in tasks {
+                output.push_str(&format!("  {}\n", format_task(task, &projects)));
+                if options.notes && !task.notes.trim().is_empty() {
+                    output.push_str(&format!("{}\n", export::indent(&task.notes, "      ")));
+                }
+            }
+        }
+        output
+    };
+
+    write_output(options, &output)
+}
+
+fn timesheet(options: &Options) -> Result<(), String> {
+    let range = date_range(options);
+    let group_by = if options.by_tag {
+        GroupBy::Tag
+    } else {
+        GroupBy::Project
+    };
+
+    let store = open_store()?;
+    let tasks = store.get_all_tasks().map_err(|err| err.to_string())?;
+    let projects = store.get_all_projects().map_err(|err| err.to_string())?;
+    let time_entries = store
+        .get_all_time_entries()
+        .map_err(|err| err.to_string())?;
+    let config = config::Config::load();
+    let timesheet = Timesheet::new(
+        &tasks,
+        &projects,
+        &time_entries,
+        range,
+        group_by,
+        &config.timesheet,
+    );
+
+    let output = if options.json {
+        serde_json::to_string_pretty(&timesheet).map_err(|err| err.to_string())? + "\n"
+    } else if options.html {
+        timesheet.to_html()
+    } else {
+        timesheet.to_csv()
+    };
+    write_output(options, &output)
+}
+
+fn export(options: &Options) -> Result<(), String> {
+    let store = open_store()?;
+    let backup = store.backup().map_err(|err| err.to_string())?;
+
+    let output = if options.csv {
+        export::tasks_csv(&backup.tasks, &backup.projects)
+    } else {
+        serde_json::to_string_pretty(&backup).map_err(|err| err.to_string())? + "\n"
+    };
+    write_output(options, &output)
+}
+
+fn import(options: &Options) -> Result<(), String> {
+    let path = match options.rest.as_slice() {
+        [path] => std::path::Path::new(path),
+        _ => return Err("expected exactly one file to import".to_owned()),
+    };
+    let backup = import::load_file(path)
+        .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+
+    let store = open_store()?;
+    let report = import::import(&store, backup, options.dry_run).map_err(|err| err.to_string())?;
+
+    if options.json {
+        return print_json(&report);
+    }
+    let verb = if report.dry_run { "would add" } else { "added" };
+    println!(
+        "{} {} tasks, {} projects and {} time entries, {} unchanged",
+        verb, report.added, report.projects_added, report.time_entries_added, report.unchanged
+    );
+    for conflict in report.conflicts.iter() {
+        println!(
+            "conflict {}: kept `{}`, skipped `{}`",
+            short_id(&conflict.id),
+            conflict.existing.trim_end(),
+            conflict.incoming.trim_end()
+        );
+    }
+    for reason in report.invalid.iter() {
+        println!("invalid: {}", reason);
+    }
+    Ok(())
+}
+
+fn write_output(options: &Options, output: &str) -> Result<(), String> {
+    match &options.out {
+        Some(path) => {
+            std::fs::write(path, output).map_err(|err| format!("failed to write {}: {}", path, err))
+        }
+        None => {
+            print!("{}", output);
+            Ok(())
+        }
+    }
+}
+
+/// Resolve the single task whose id starts with the given prefix.
+fn find_by_prefix(tasks: Vec<Task>, options: &Options) -> Result<Task, String> {
+    match options.rest.as_slice() {
+        [prefix] => match_prefix(tasks, prefix),
+        _ => Err("expected exactly one task id prefix".to_owned()),
+    }
+}
+
+fn match_prefix(tasks: Vec<Task>, prefix: &str) -> Result<Task, String> {
+    let mut matches: Vec<Task> = tasks
+        .into_iter()
+        .filter(|task| task.id.starts_with(prefix))
+        .collect();
+
+    match matches.len() {
+        0 => Err(format!("no task matches `{}`", prefix)),
+        1 => Ok(matches.remove(0)),
+        n => Err(format!("`{}` is ambiguous, it matches {} tasks", prefix, n)),
+    }
+}
+
+fn format_task(task: &Task, projects: &[Project]) -> String {
+    let mut line = format!(
+        "{} [{}] {}",
+        short_id(&task.id),
+        if task.completed { "x" } else { " " },
+        task.description.trim_end()
+    );
+    if let Some(project) = task
+        .project_id
+        .as_ref()
+        .and_then(|id| projects.iter().find(|p| &p.id == id))
+    {
+        line.push_str(&format!(" @{}", project.name));
+    }
+    if let Some(token) = task.priority.token() {
+        line.push_str(&format!(" {}", token));
+    }
+    if let (Some(due_at), Some(due_day)) = (task.due_at, task.due_day()) {
+        line.push_str(&format!(" due {}", due_day));
+        if day::has_time(due_at) {
+            line.push_str(&format!(" {}", day::time_of(due_at)));
+        }
+    }
+    if let Some(recurrence) = &task.recurrence {
+        line.push_str(&format!(" ({})", recurrence.describe().to_lowercase()));
+    }
+    for tag in task.tags.iter() {
+        line.push_str(&format!(" #{}", tag));
+    }
+    line
+}
+
+fn short_id(id: &str) -> &str {
+    id.get(..8).unwrap_or(id)
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value).map_err(|err| err.to_string())?;
+    println!("{}", json);
+    Ok(())
+}
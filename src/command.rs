@@ -0,0 +1,109 @@
+//! Task changes as undoable commands. Each command records the task before
+//! and after it ran; undoing puts the `before` state back, redoing the `after`
+//! state. The log lives in the database, so the app and the CLI share it.
+
+use crate::types::{self, Task};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandKind {
+    Add,
+    Update,
+    Complete,
+    Delete,
+}
+
+impl CommandKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommandKind::Add => "add",
+            CommandKind::Update => "update",
+            CommandKind::Complete => "complete",
+            CommandKind::Delete => "delete",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        [
+            CommandKind::Add,
+            CommandKind::Update,
+            CommandKind::Complete,
+            CommandKind::Delete,
+        ]
+        .into_iter()
+        .find(|k| k.as_str() == kind)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    pub kind: CommandKind,
+    /// `None` when the task did not exist before, i.e. for `Add`.
+    pub before: Option<Task>,
+    pub after: Option<Task>,
+}
+
+impl Command {
+    pub fn add(task: Task) -> Self {
+        Command {
+            kind: CommandKind::Add,
+            before: None,
+            after: Some(task),
+        }
+    }
+
+    /// An edit, counted as completing (or reopening) when that is what changed.
+    pub fn update(before: Task, after: Task) -> Self {
+        let kind = if before.completed != after.completed {
+            CommandKind::Complete
+        } else {
+            CommandKind::Update
+        };
+        Command {
+            kind,
+            before: Some(before),
+            after: Some(after),
+        }
+    }
+
+    /// Move a task to the trash.
+    pub fn delete(task: Task) -> Self {
+        let mut after = task.clone();
+        after.deleted_at = Some(types::now());
+        Command {
+            kind: CommandKind::Delete,
+            before: Some(task),
+            after: Some(after),
+        }
+    }
+
+    pub fn task_id(&self) -> &str {
+        self.after
+            .as_ref()
+            .or(self.before.as_ref())
+            .map_or("", |task| task.id.as_str())
+    }
+
+    /// Whether the change is easy to miss and worth offering an undo for.
+    pub fn is_destructive(&self) -> bool {
+        matches!(self.kind, CommandKind::Delete | CommandKind::Complete)
+    }
+
+    /// What the command did, e.g. `Deleted "write report"`.
+    pub fn describe(&self) -> String {
+        let verb = match self.kind {
+            CommandKind::Add => "Added",
+            CommandKind::Update => "Edited",
+            CommandKind::Complete => match &self.after {
+                Some(task) if !task.completed => "Reopened",
+                _ => "Completed",
+            },
+            CommandKind::Delete => "Deleted",
+        };
+        let description = self
+            .after
+            .as_ref()
+            .or(self.before.as_ref())
+            .map_or("", |task| task.description.trim_end());
+        format!("{} \"{}\"", verb, description)
+    }
+}
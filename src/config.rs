@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+const CONFIG_DIR: &str = "small-worklog";
+const CONFIG_FILE: &str = "config.toml";
+const DATABASE_FILE: &str = "small-worklog.db";
+
+/// Environment variable overriding the database path, like `--db`.
+pub const DATABASE_ENV: &str = "SMALL_WORKLOG_DB";
+
+static DATABASE_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// The user's settings, stored as TOML under the platform config directory,
+/// e.g. `~/.config/small-worklog/config.toml` on Linux:
+///
+/// ```toml
+/// database_path = "/home/me/Documents/worklog.db"
+/// active_profile = "work"
+///
+/// [profiles]
+/// work = "/home/me/work.db"
+/// personal = "/home/me/personal.db"
+///
+/// [timesheet]
+/// currency = "EUR"
+/// default_rate = 80.0
+/// round_to_minutes = 15
+///
+/// [timesheet.project_rates]
+/// acme = 120.0
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Config {
+    /// The database used when no profile is active.
+    pub database_path: Option<PathBuf>,
+    pub active_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, PathBuf>,
+    #[serde(default)]
+    pub timesheet: TimesheetConfig,
+}
+
+/// Hourly rates and rounding for timesheets. Rates are looked up by project
+/// or tag name, ignoring case, falling back to `default_rate`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct TimesheetConfig {
+    pub currency: String,
+    pub default_rate: f64,
+    /// Round each row up to this many minutes, 0 for whole minutes.
+    pub round_to_minutes: u32,
+    pub project_rates: BTreeMap<String, f64>,
+    pub tag_rates: BTreeMap<String, f64>,
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(CONFIG_DIR).join(CONFIG_FILE))
+    }
+
+    /// Load the config file, falling back to the defaults when it does not
+    /// exist or cannot be parsed.
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content).unwrap_or_else(|err| {
+                eprintln!("ignoring invalid config {}: {}", path.display(), err);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let path = Self::path().ok_or(Error::new(
+            ErrorKind::Other,
+            "Failed to get config directory",
+        ))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content =
+            toml::to_string_pretty(self).map_err(|err| Error::new(ErrorKind::Other, err))?;
+        fs::write(path, content)
+    }
+
+    /// The database of the active profile, or the default database.
+    pub fn database_path(&self) -> PathBuf {
+        self.active_profile
+            .as_ref()
+            .and_then(|name| self.profiles.get(name))
+            .or(self.database_path.as_ref())
+            .cloned()
+            .unwrap_or_else(default_database_path)
+    }
+
+    /// A new profile gets its own database next to the config file, named
+    /// after the profile but never outside that directory or shared with
+    /// another profile.
+    pub fn add_profile(&mut self, name: &str) -> PathBuf {
+        if let Some(path) = self.profiles.get(name) {
+            return path.clone();
+        }
+        let dir = Self::path()
+            .and_then(|path| path.parent().map(|dir| dir.to_path_buf()))
+            .unwrap_or_else(|| dirs::home_dir().unwrap_or_default());
+        let stem = file_stem(name);
+        let taken = |path: &PathBuf| self.profiles.values().any(|p| p == path);
+        let path = (1..)
+            .map(|n| match n {
+                1 => dir.join(format!("{}.db", stem)),
+                n => dir.join(format!("{}-{}.db", stem, n)),
+            })
+            .find(|path| !taken(path))
+            .unwrap_or_default();
+        self.profiles.insert(name.to_owned(), path.clone());
+        path
+    }
+}
+
+/// A profile name cut down to letters, digits, `-` and `_`, for file names.
+fn file_stem(name: &str) -> String {
+    let mut stem = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            stem.push(c);
+        } else if !stem.is_empty() && !stem.ends_with('-') {
+            stem.push('-');
+        }
+    }
+    let stem = stem.trim_end_matches('-');
+    if stem.is_empty() {
+        "profile".to_owned()
+    } else {
+        stem.to_owned()
+    }
+}
+
+/// Where the database lived before it was configurable.
+pub fn default_database_path() -> PathBuf {
+    let mut path = dirs::home_dir().unwrap_or_default();
+    path.push(DATABASE_FILE);
+    path
+}
+
+/// Set from the `--db` command line option, before any store is opened.
+pub fn set_database_override(path: PathBuf) {
+    let _ = DATABASE_OVERRIDE.set(path);
+}
+
+/// Whether the database is pinned by `--db` or the environment, in which case
+/// profiles cannot be switched.
+pub fn database_overridden() -> bool {
+    DATABASE_OVERRIDE.get().is_some() || std::env::var_os(DATABASE_ENV).is_some()
+}
+
+/// The database to open: `--db`, then `SMALL_WORKLOG_DB`, then the config file.
+pub fn database_path() -> PathBuf {
+    if let Some(path) = DATABASE_OVERRIDE.get() {
+        return path.clone();
+    }
+    if let Some(path) = std::env::var_os(DATABASE_ENV) {
+        return PathBuf::from(path);
+    }
+    Config::load().database_path()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_names_become_plain_file_stems() {
+        let cases = [
+            ("work", "work"),
+            ("Side project", "Side-project"),
+            ("client_a", "client_a"),
+            ("../../etc/passwd", "etc-passwd"),
+            ("/tmp/x", "tmp-x"),
+            ("C:\\Users\\me", "C-Users-me"),
+            ("a.db", "a-db"),
+            ("...", "profile"),
+            ("", "profile"),
+            ("Café", "Café"),
+        ];
+        for (name, stem) in cases {
+            assert_eq!(file_stem(name), stem, "{name:?}");
+        }
+    }
+
+    #[test]
+    fn profiles_get_their_own_database_next_to_each_other() {
+        let mut config = Config::default();
+        let work = config.add_profile("work");
+        let escaped = config.add_profile("../work");
+        let spaced = config.add_profile("work!");
+        assert_eq!(escaped.parent(), work.parent());
+        assert_eq!(escaped.file_name().unwrap(), "work-2.db");
+        assert_eq!(spaced.file_name().unwrap(), "work-3.db");
+        assert_eq!(config.add_profile("work"), work);
+        assert_eq!(config.profiles.len(), 3);
+    }
+}
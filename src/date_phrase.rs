@@ -0,0 +1,524 @@
+//! Dates written the way people say them, like `tomorrow 3pm`, `next fri`,
+//! `in 2 weeks` or `2026-03-01`, found among the words of the quick-add box.
+
+use chrono::{Datelike, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use std::ops::Range;
+
+/// Words that may lead a date, like "due fri" or "by tomorrow". They are
+/// removed together with the date.
+const CONNECTORS: &[&str] = &["on", "by", "due", "until"];
+
+/// What `tonight` means.
+const TONIGHT: (u32, u32) = (20, 0);
+
+/// A date phrase found in a list of words.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatePhrase {
+    /// The words the phrase spans, to be taken out of the description.
+    pub words: Range<usize>,
+    pub date: NaiveDate,
+    pub time: Option<NaiveTime>,
+}
+
+/// Find the first date phrase among `words`. `today` is the current working
+/// day, `now` the local wall clock time that decides whether a time without a
+/// date, like `3pm`, is still today or already tomorrow.
+pub fn find(words: &[&str], today: NaiveDate, now: NaiveDateTime) -> Option<DatePhrase> {
+    let words: Vec<String> = words.iter().map(|word| normalize(word)).collect();
+    (0..words.len()).find_map(|start| phrase_at(&words, start, today, now))
+}
+
+fn normalize(word: &str) -> String {
+    word.trim_end_matches([',', '.', ';', '!', '?'])
+        .to_lowercase()
+}
+
+/// A date and/or a time starting at `start`, in either order: `tomorrow 3pm`,
+/// `at 3pm tomorrow`, `by fri`.
+fn phrase_at(
+    words: &[String],
+    start: usize,
+    today: NaiveDate,
+    now: NaiveDateTime,
+) -> Option<DatePhrase> {
+    let mut end = start;
+    let connector = words
+        .get(end)
+        .is_some_and(|word| CONNECTORS.contains(&word.as_str()));
+    if connector {
+        end += 1;
+    }
+
+    let mut time = None;
+    if let Some((found, len)) = time_at(words, end, false) {
+        time = Some(found);
+        end += len;
+    }
+    // a time may be followed by its own connector, like `at 3pm on fri`
+    let mut date_start = end;
+    let mut date_connector = connector;
+    if time.is_some()
+        && words
+            .get(end)
+            .is_some_and(|word| CONNECTORS.contains(&word.as_str()))
+        && date_at(words, end + 1, today, true).is_some()
+    {
+        date_start += 1;
+        date_connector = true;
+    }
+    let mut date = None;
+    if let Some((found, len, default_time)) = date_at(words, date_start, today, date_connector) {
+        date = Some(found);
+        end = date_start + len;
+        if time.is_none() {
+            match time_at(words, end, true) {
+                Some((found, len)) => {
+                    time = Some(found);
+                    end += len;
+                }
+                None => time = default_time,
+            }
+        }
+    }
+
+    let date = match (date, time) {
+        (Some(date), _) => date,
+        // a bare time is the next time the clock shows it
+        (None, Some(time)) if today.and_time(time) > now => today,
+        (None, Some(_)) => today + Duration::days(1),
+        (None, None) => return None,
+    };
+    Some(DatePhrase {
+        words: start..end,
+        date,
+        time,
+    })
+}
+
+/// A date at `index` and the number of words it takes, plus the time it
+/// implies, if any. Bare `sat`, `sun`, `may` and `march` are common words and
+/// only count as dates after a connector.
+fn date_at(
+    words: &[String],
+    index: usize,
+    today: NaiveDate,
+    connector: bool,
+) -> Option<(NaiveDate, usize, Option<NaiveTime>)> {
+    let word = words.get(index)?.as_str();
+    let next = words.get(index + 1).map(String::as_str);
+    let date = match word {
+        "today" => (today, 1),
+        "tonight" => {
+            let time = NaiveTime::from_hms_opt(TONIGHT.0, TONIGHT.1, 0);
+            return Some((today, 1, time));
+        }
+        "tomorrow" | "tmr" | "tmrw" => (today + Duration::days(1), 1),
+        "next" => match next? {
+            "week" => (coming(today, Weekday::Mon), 2),
+            "month" => (today.with_day(1)? + Months::new(1), 2),
+            "year" => (NaiveDate::from_ymd_opt(today.year() + 1, 1, 1)?, 2),
+            // that day in the week after this one
+            day => {
+                let offset = weekday(day)?.num_days_from_monday() as i64;
+                (coming(today, Weekday::Mon) + Duration::days(offset), 2)
+            }
+        },
+        "this" => {
+            let weekday = weekday(next?)?;
+            let days =
+                (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+            (today + Duration::days(days as i64), 2)
+        }
+        "in" => {
+            let count = count(next?)?;
+            // counts too large for a date are not a date
+            let date = match words.get(index + 2)?.as_str() {
+                "day" | "days" => today.checked_add_signed(Duration::try_days(count)?)?,
+                "week" | "weeks" => today.checked_add_signed(Duration::try_weeks(count)?)?,
+                "month" | "months" => {
+                    today.checked_add_months(Months::new(u32::try_from(count).ok()?))?
+                }
+                _ => return None,
+            };
+            (date, 3)
+        }
+        "sat" | "sun" if !connector => return None,
+        _ => {
+            let month =
+                |word: &str| month(word).filter(|_| connector || !matches!(word, "may" | "march"));
+            if let Some(weekday) = weekday(word) {
+                (coming(today, weekday), 1)
+            } else if let Ok(date) = NaiveDate::parse_from_str(word, "%Y-%m-%d") {
+                (date, 1)
+            } else if let (Some(month), Some(day)) = (month(word), next.and_then(day_of_month)) {
+                (month_day(today, month, day)?, 2)
+            } else if let (Some(day), Some(month)) = (day_of_month(word), next.and_then(month)) {
+                (month_day(today, month, day)?, 2)
+            } else {
+                return None;
+            }
+        }
+    };
+    Some((date.0, date.1, None))
+}
+
+/// A time at `index`, optionally after `at`, and the number of words it
+/// takes: `3pm`, `3:30 pm`, `at 15:00`, `noon`. Bare numbers are not times,
+/// and neither is a clock like `3:16` without `at`, `am` or `pm`, unless it
+/// follows a date.
+fn time_at(words: &[String], index: usize, after_date: bool) -> Option<(NaiveTime, usize)> {
+    let skip = usize::from(words.get(index).is_some_and(|word| word == "at"));
+    let word = words.get(index + skip)?.as_str();
+    if word == "noon" {
+        return Some((NaiveTime::from_hms_opt(12, 0, 0)?, skip + 1));
+    }
+
+    let (clock, meridiem, len) = match meridiem_suffix(word) {
+        Some((clock, pm)) => (clock, Some(pm), 1),
+        None => match words.get(index + skip + 1).map(String::as_str) {
+            Some("am") => (word, Some(false), 2),
+            Some("pm") => (word, Some(true), 2),
+            _ => (word, None, 1),
+        },
+    };
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) if minute.len() == 2 => (hour.parse().ok()?, minute.parse().ok()?),
+        // `15` alone is a number, not a time
+        Some(_) => return None,
+        None if meridiem.is_some() => (clock.parse().ok()?, 0),
+        None => return None,
+    };
+    if meridiem.is_none() && skip == 0 && !after_date {
+        return None;
+    }
+    let hour = match meridiem {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(pm) => hour % 12 + if pm { 12 } else { 0 },
+        None => hour,
+    };
+    Some((NaiveTime::from_hms_opt(hour, minute, 0)?, skip + len))
+}
+
+/// `3pm` → (`3`, true), `10:30am` → (`10:30`, false).
+fn meridiem_suffix(word: &str) -> Option<(&str, bool)> {
+    let (clock, pm) = if let Some(clock) = word.strip_suffix("pm") {
+        (clock, true)
+    } else {
+        (word.strip_suffix("am")?, false)
+    };
+    (!clock.is_empty()).then_some((clock, pm))
+}
+
+/// The next `weekday` after today, a week away if today is that day.
+fn coming(today: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let days =
+        (weekday.num_days_from_monday() + 6 - today.weekday().num_days_from_monday()) % 7 + 1;
+    today + Duration::days(days as i64)
+}
+
+fn weekday(word: &str) -> Option<Weekday> {
+    Some(match word {
+        "monday" | "mon" => Weekday::Mon,
+        "tuesday" | "tue" | "tues" => Weekday::Tue,
+        "wednesday" | "wed" => Weekday::Wed,
+        "thursday" | "thu" | "thur" | "thurs" => Weekday::Thu,
+        "friday" | "fri" => Weekday::Fri,
+        "saturday" | "sat" => Weekday::Sat,
+        "sunday" | "sun" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+fn month(word: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = [
+        "january",
+        "february",
+        "march",
+        "april",
+        "may",
+        "june",
+        "july",
+        "august",
+        "september",
+        "october",
+        "november",
+        "december",
+    ];
+    let position = MONTHS
+        .iter()
+        .position(|month| word == *month || (word.len() >= 3 && month.starts_with(word)))?;
+    Some(position as u32 + 1)
+}
+
+/// `20`, `20th`, `1st`.
+fn day_of_month(word: &str) -> Option<u32> {
+    let digits = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let suffix = &word[digits.len()..];
+    if !matches!(suffix, "" | "st" | "nd" | "rd" | "th") {
+        return None;
+    }
+    digits.parse().ok().filter(|day| (1..=31).contains(day))
+}
+
+/// A month and day in the coming year, e.g. `jan 5` in December is next January.
+fn month_day(today: NaiveDate, month: u32, day: u32) -> Option<NaiveDate> {
+    let date = NaiveDate::from_ymd_opt(today.year(), month, day)?;
+    if date < today {
+        NaiveDate::from_ymd_opt(today.year() + 1, month, day)
+    } else {
+        Some(date)
+    }
+}
+
+/// `2`, `a`, `one` up to `ten`.
+fn count(word: &str) -> Option<i64> {
+    const NUMBERS: [&str; 10] = [
+        "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    ];
+    match word {
+        "a" | "an" => Some(1),
+        _ => word.parse().ok().or_else(|| {
+            NUMBERS
+                .iter()
+                .position(|number| *number == word)
+                .map(|index| index as i64 + 1)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wednesday.
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, 14).unwrap()
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn time(hour: u32, minute: u32) -> Option<NaiveTime> {
+        NaiveTime::from_hms_opt(hour, minute, 0)
+    }
+
+    /// The phrase in `text` on `today` at 10:00, and the words left over.
+    fn parse_on(text: &str, today: NaiveDate) -> Option<(NaiveDate, Option<NaiveTime>, String)> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let now = today.and_hms_opt(10, 0, 0).unwrap();
+        let phrase = find(&words, today, now)?;
+        let mut rest = words.clone();
+        rest.drain(phrase.words);
+        Some((phrase.date, phrase.time, rest.join(" ")))
+    }
+
+    fn parse(text: &str) -> Option<(NaiveDate, Option<NaiveTime>, String)> {
+        parse_on(text, today())
+    }
+
+    #[test]
+    fn reads_phrases() {
+        let cases = [
+            // relative days
+            (
+                "write report today",
+                date(2026, 10, 14),
+                None,
+                "write report",
+            ),
+            (
+                "call vendor tomorrow",
+                date(2026, 10, 15),
+                None,
+                "call vendor",
+            ),
+            ("call vendor tmrw", date(2026, 10, 15), None, "call vendor"),
+            ("deploy tonight", date(2026, 10, 14), time(20, 0), "deploy"),
+            // weekdays: the coming one, and the one in next week
+            ("review PR fri", date(2026, 10, 16), None, "review PR"),
+            ("review PR next fri", date(2026, 10, 23), None, "review PR"),
+            (
+                "review PR next monday",
+                date(2026, 10, 19),
+                None,
+                "review PR",
+            ),
+            ("standup wed", date(2026, 10, 21), None, "standup"),
+            ("standup this wed", date(2026, 10, 14), None, "standup"),
+            ("demo this friday", date(2026, 10, 16), None, "demo"),
+            ("plan next week", date(2026, 10, 19), None, "plan"),
+            ("plan next month", date(2026, 11, 1), None, "plan"),
+            ("plan next year", date(2027, 1, 1), None, "plan"),
+            // counted
+            (
+                "renew license in 2 weeks",
+                date(2026, 10, 28),
+                None,
+                "renew license",
+            ),
+            (
+                "renew license in a week",
+                date(2026, 10, 21),
+                None,
+                "renew license",
+            ),
+            (
+                "follow up in three days",
+                date(2026, 10, 17),
+                None,
+                "follow up",
+            ),
+            ("invoice in 1 month", date(2026, 11, 14), None, "invoice"),
+            // calendar dates, rolling over into next year once passed
+            ("release 2026-12-01", date(2026, 12, 1), None, "release"),
+            ("pay rent on oct 31", date(2026, 10, 31), None, "pay rent"),
+            (
+                "pay rent on 31st october",
+                date(2026, 10, 31),
+                None,
+                "pay rent",
+            ),
+            ("renew domain jan 5", date(2027, 1, 5), None, "renew domain"),
+            ("taxes by march 1st", date(2027, 3, 1), None, "taxes"),
+            // times, with and without a date
+            (
+                "call vendor tomorrow 3pm",
+                date(2026, 10, 15),
+                time(15, 0),
+                "call vendor",
+            ),
+            (
+                "call vendor tomorrow at 3:30 pm",
+                date(2026, 10, 15),
+                time(15, 30),
+                "call vendor",
+            ),
+            ("sync at 15:00", date(2026, 10, 14), time(15, 0), "sync"),
+            (
+                "call vendor tomorrow 15:00",
+                date(2026, 10, 15),
+                time(15, 0),
+                "call vendor",
+            ),
+            ("lunch noon", date(2026, 10, 14), time(12, 0), "lunch"),
+            (
+                "release 2026-12-01 at 09:30",
+                date(2026, 12, 1),
+                time(9, 30),
+                "release",
+            ),
+            (
+                "at 9am tomorrow standup",
+                date(2026, 10, 15),
+                time(9, 0),
+                "standup",
+            ),
+            ("12am backup", date(2026, 10, 15), time(0, 0), "backup"),
+            // a time, then a connector and a date
+            (
+                "meet at 3pm on fri",
+                date(2026, 10, 16),
+                time(15, 0),
+                "meet",
+            ),
+            (
+                "call at 3pm by phone",
+                date(2026, 10, 14),
+                time(15, 0),
+                "call by phone",
+            ),
+            // a bare time that has passed is tomorrow's
+            ("standup 9am", date(2026, 10, 15), time(9, 0), "standup"),
+            ("standup 11am", date(2026, 10, 14), time(11, 0), "standup"),
+            // weekend days only after a connector
+            ("brunch on sat", date(2026, 10, 17), None, "brunch"),
+            ("deploy by sun", date(2026, 10, 18), None, "deploy"),
+            ("ship on may 2", date(2027, 5, 2), None, "ship"),
+            (
+                "report due friday, please",
+                date(2026, 10, 16),
+                None,
+                "report please",
+            ),
+        ];
+        for (text, expected_date, expected_time, rest) in cases {
+            let (found_date, found_time, found_rest) =
+                parse(text).unwrap_or_else(|| panic!("no date in `{}`", text));
+            assert_eq!(found_date, expected_date, "date of `{}`", text);
+            assert_eq!(found_time, expected_time, "time of `{}`", text);
+            assert_eq!(found_rest, rest, "rest of `{}`", text);
+        }
+    }
+
+    #[test]
+    fn ignores_non_dates() {
+        for text in [
+            "fix issue 15",
+            "bump to 15:0",
+            "sat on the review",
+            "sun glare on the dashboard",
+            "read chapter 13pm",
+            "in review",
+            "in many days",
+            "next thing",
+            "may the build pass",
+            "may 2 people join",
+            "2 may help",
+            "John 3:16",
+            "march 3 miles",
+            "15",
+        ] {
+            assert_eq!(parse(text), None, "`{}`", text);
+        }
+    }
+
+    #[test]
+    fn next_weekday_from_sunday() {
+        let sunday = date(2026, 10, 18);
+        assert_eq!(parse_on("x mon", sunday).unwrap().0, date(2026, 10, 19));
+        // weeks start on Monday, so next week starts tomorrow
+        assert_eq!(
+            parse_on("x next mon", sunday).unwrap().0,
+            date(2026, 10, 19)
+        );
+        assert_eq!(
+            parse_on("x next sun", sunday).unwrap().0,
+            date(2026, 10, 25)
+        );
+        assert_eq!(parse_on("x this sun", sunday).unwrap().0, sunday);
+    }
+
+    #[test]
+    fn rolls_over_the_year() {
+        let new_years_eve = date(2026, 12, 31);
+        assert_eq!(
+            parse_on("x tomorrow", new_years_eve).unwrap().0,
+            date(2027, 1, 1)
+        );
+        assert_eq!(
+            parse_on("x next month", new_years_eve).unwrap().0,
+            date(2027, 1, 1)
+        );
+        assert_eq!(
+            parse_on("x dec 30", new_years_eve).unwrap().0,
+            date(2027, 12, 30)
+        );
+        assert_eq!(
+            parse_on("x in 2 months", new_years_eve).unwrap().0,
+            date(2027, 2, 28)
+        );
+    }
+
+    #[test]
+    fn counts_too_large_for_a_date_are_not_dates() {
+        for text in [
+            "ship in 1000000000 days",
+            "ship in 99999999999999999 weeks",
+            "ship in 9999999999 months",
+            "ship in 4000000000 months",
+        ] {
+            assert_eq!(parse(text), None, "`{}`", text);
+        }
+    }
+}
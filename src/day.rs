@@ -0,0 +1,202 @@
+//! The one place that decides which day a moment belongs to.
+//!
+//! Days follow the local wall clock, optionally starting later than midnight
+//! so that work done at 1am still counts towards the previous day. Working on
+//! the wall clock rather than on fixed 24 hour steps keeps the 23 and 25 hour
+//! days around DST transitions on the right date.
+
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Settings key for the hour local days start at.
+pub const DAY_START_SETTING: &str = "day_start_hour";
+
+static DAY_START_HOUR: AtomicU32 = AtomicU32::new(0);
+
+/// The hour (0-23) the working day starts at.
+pub fn day_start_hour() -> u32 {
+    DAY_START_HOUR.load(Ordering::Relaxed)
+}
+
+pub fn set_day_start_hour(hour: u32) {
+    DAY_START_HOUR.store(hour.min(23), Ordering::Relaxed);
+}
+
+/// The day a unix timestamp belongs to in the given time zone.
+pub fn day_in<Tz: TimeZone>(timestamp: i64, tz: &Tz, day_start_hour: u32) -> NaiveDate {
+    let utc = DateTime::from_timestamp(timestamp, 0).unwrap_or_default();
+    let wall_clock = utc.with_timezone(tz).naive_local();
+    (wall_clock - Duration::hours(day_start_hour as i64)).date()
+}
+
+/// The local day a unix timestamp belongs to.
+pub fn day_of(timestamp: u64) -> NaiveDate {
+    day_in(timestamp as i64, &Local, day_start_hour())
+}
+
+/// The local day it is now.
+pub fn today() -> NaiveDate {
+    day_in(Local::now().timestamp(), &Local, day_start_hour())
+}
+
+/// The moment a day starts, as a unix timestamp. Due dates without a time
+/// are stored as the start of their day.
+pub fn start_of(date: NaiveDate) -> u64 {
+    start_in(date, &Local, day_start_hour())
+}
+
+/// The moment a day starts in the given time zone.
+pub fn start_in<Tz: TimeZone>(date: NaiveDate, tz: &Tz, day_start_hour: u32) -> u64 {
+    timestamp_in(
+        date.and_hms_opt(day_start_hour, 0, 0).unwrap_or_default(),
+        tz,
+    )
+}
+
+/// A local wall clock date and time as a unix timestamp.
+pub fn at(date: NaiveDate, time: NaiveTime) -> u64 {
+    timestamp_in(date.and_time(time), &Local)
+}
+
+fn timestamp_in<Tz: TimeZone>(wall_clock: NaiveDateTime, tz: &Tz) -> u64 {
+    tz.from_local_datetime(&wall_clock)
+        .earliest()
+        // the time fell into a DST gap, take it once the clock resumes
+        .or_else(|| {
+            tz.from_local_datetime(&(wall_clock + Duration::hours(1)))
+                .earliest()
+        })
+        .map_or(0, |time| time.timestamp().max(0) as u64)
+}
+
+/// Whether a timestamp has a time of day, i.e. is not the start of its day.
+pub fn has_time(timestamp: u64) -> bool {
+    has_time_in(timestamp, &Local, day_start_hour())
+}
+
+fn has_time_in<Tz: TimeZone>(timestamp: u64, tz: &Tz, day_start_hour: u32) -> bool {
+    start_in(
+        day_in(timestamp as i64, tz, day_start_hour),
+        tz,
+        day_start_hour,
+    ) != timestamp
+}
+
+/// The local wall clock time of a timestamp, like `15:30`.
+pub fn time_of(timestamp: u64) -> String {
+    Local
+        .timestamp_opt(timestamp as i64, 0)
+        .earliest()
+        .map_or_else(String::new, |time| time.format("%H:%M").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+    use chrono_tz::America::New_York;
+    use chrono_tz::Europe::Berlin;
+
+    const HOUR: u64 = 60 * 60;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn timestamp(tz: &impl TimeZone, wall_clock: &str) -> i64 {
+        let wall_clock = NaiveDateTime::parse_from_str(wall_clock, "%Y-%m-%d %H:%M").unwrap();
+        tz.from_local_datetime(&wall_clock)
+            .earliest()
+            .unwrap()
+            .timestamp()
+    }
+
+    #[test]
+    fn days_start_at_the_day_start_hour() {
+        let tz = FixedOffset::east_opt(2 * 60 * 60).unwrap();
+        let cases = [
+            ("2026-10-14 00:00", 0, date(2026, 10, 14)),
+            ("2026-10-13 23:59", 0, date(2026, 10, 13)),
+            ("2026-10-14 03:59", 4, date(2026, 10, 13)),
+            ("2026-10-14 04:00", 4, date(2026, 10, 14)),
+            ("2026-10-14 23:59", 4, date(2026, 10, 14)),
+            ("2027-01-01 01:00", 4, date(2026, 12, 31)),
+        ];
+        for (wall_clock, day_start_hour, expected) in cases {
+            let timestamp = timestamp(&tz, wall_clock);
+            assert_eq!(
+                day_in(timestamp, &tz, day_start_hour),
+                expected,
+                "{wall_clock} with days starting at {day_start_hour}"
+            );
+        }
+        assert_eq!(
+            start_in(date(2026, 10, 14), &tz, 4) as i64,
+            timestamp(&tz, "2026-10-14 04:00")
+        );
+    }
+
+    #[test]
+    fn dst_transitions_make_23_and_25_hour_days() {
+        // Berlin skips an hour at 02:00 on 2026-03-29 and repeats one at
+        // 03:00 on 2026-10-25, so a day starting at 4am is short or long the
+        // day before
+        let cases = [
+            (0, date(2026, 3, 29), 23),
+            (0, date(2026, 10, 25), 25),
+            (0, date(2026, 10, 26), 24),
+            (4, date(2026, 3, 28), 23),
+            (4, date(2026, 3, 29), 24),
+            (4, date(2026, 10, 24), 25),
+        ];
+        for (day_start_hour, day, hours) in cases {
+            let next_day = day.succ_opt().unwrap();
+            let start = start_in(day, &Berlin, day_start_hour);
+            let end = start_in(next_day, &Berlin, day_start_hour);
+            assert_eq!(
+                end - start,
+                hours * HOUR,
+                "{day} starting at {day_start_hour}"
+            );
+            assert_eq!(day_in(start as i64, &Berlin, day_start_hour), day);
+            assert_eq!(day_in(end as i64 - 1, &Berlin, day_start_hour), day);
+            assert_eq!(day_in(end as i64, &Berlin, day_start_hour), next_day);
+        }
+    }
+
+    #[test]
+    fn a_day_starting_in_a_dst_gap_starts_when_the_clock_resumes() {
+        // clocks jump from 02:00 to 03:00
+        assert_eq!(
+            start_in(date(2026, 3, 29), &Berlin, 2) as i64,
+            timestamp(&Berlin, "2026-03-29 03:00")
+        );
+        assert_eq!(
+            start_in(date(2026, 3, 8), &New_York, 2) as i64,
+            timestamp(&New_York, "2026-03-08 03:00")
+        );
+        // a day starting at a repeated hour starts the first time round
+        assert_eq!(
+            start_in(date(2026, 10, 25), &Berlin, 2),
+            start_in(date(2026, 10, 25), &Berlin, 0) + 2 * HOUR
+        );
+    }
+
+    #[test]
+    fn only_the_start_of_a_day_has_no_time() {
+        let tz = FixedOffset::west_opt(5 * 60 * 60).unwrap();
+        let start = start_in(date(2026, 10, 14), &tz, 0);
+        assert!(!has_time_in(start, &tz, 0));
+        assert!(has_time_in(start + 1, &tz, 0));
+        assert!(has_time_in(start + 15 * HOUR, &tz, 0));
+        assert!(has_time_in(start, &tz, 4));
+        assert!(!has_time_in(start + 4 * HOUR, &tz, 4));
+
+        let gap_day = start_in(date(2026, 3, 29), &Berlin, 2);
+        assert!(!has_time_in(gap_day, &Berlin, 2));
+        assert!(has_time_in(gap_day - HOUR, &Berlin, 2));
+        let long_day = start_in(date(2026, 10, 25), &Berlin, 2);
+        assert!(!has_time_in(long_day, &Berlin, 2));
+        assert!(has_time_in(long_day + HOUR, &Berlin, 2));
+    }
+}
@@ -0,0 +1,64 @@
+use rusqlite::ErrorCode;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can go wrong talking to the database.
+#[derive(Debug)]
+pub enum StoreError {
+    /// Another process kept the database locked for longer than we retried.
+    Busy,
+    /// The database file or its directory cannot be written to.
+    ReadOnly,
+    /// The database was written by a newer version of small-worklog.
+    SchemaTooNew {
+        found: u32,
+        supported: u32,
+    },
+    Sqlite(rusqlite::Error),
+    /// The tasks file of v1 exists but cannot be read, so it was not imported.
+    V1File(PathBuf, io::Error),
+}
+
+pub type Result<T, E = StoreError> = std::result::Result<T, E>;
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(err: rusqlite::Error) -> Self {
+        match err.sqlite_error_code() {
+            Some(ErrorCode::DatabaseBusy) | Some(ErrorCode::DatabaseLocked) => StoreError::Busy,
+            Some(ErrorCode::ReadOnly) => StoreError::ReadOnly,
+            _ => StoreError::Sqlite(err),
+        }
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Busy => write!(f, "the database is locked by another program"),
+            StoreError::ReadOnly => write!(f, "the database is read-only"),
+            StoreError::SchemaTooNew { found, supported } => write!(
+                f,
+                "database schema version {} is newer than supported version {}, please upgrade small-worklog",
+                found, supported
+            ),
+            StoreError::Sqlite(err) => write!(f, "{}", err),
+            StoreError::V1File(path, err) => write!(
+                f,
+                "failed to import the tasks of small-worklog v1 from {}: {}",
+                path.display(),
+                err
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StoreError::Sqlite(err) => Some(err),
+            StoreError::V1File(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
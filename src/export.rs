@@ -0,0 +1,275 @@
+use crate::day;
+use crate::types::{Project, Task, TimeEntry};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Bump when the backup layout changes in a way older readers cannot handle.
+pub const BACKUP_VERSION: u32 = 1;
+
+/// The columns of CSV exports. Exports made before priorities, due dates,
+/// subtasks, notes and the trash were included stop after `tags`.
+pub const CSV_HEADER: [&str; 14] = [
+    "id",
+    "description",
+    "completed",
+    "created_at",
+    "created_at_date",
+    "completed_at",
+    "project",
+    "tags",
+    "priority",
+    "due_at",
+    "recurrence",
+    "parent_id",
+    "notes",
+    "deleted_at",
+];
+
+/// Everything in the database, as written by `export --json` and read back by `import`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Backup {
+    pub version: u32,
+    #[serde(default)]
+    pub exported_at: u64,
+    #[serde(default)]
+    pub projects: Vec<Project>,
+    pub tasks: Vec<Task>,
+    #[serde(default)]
+    pub time_entries: Vec<TimeEntry>,
+}
+
+impl Backup {
+    pub fn new(tasks: Vec<Task>, projects: Vec<Project>) -> Self {
+        let exported_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        Backup {
+            version: BACKUP_VERSION,
+            exported_at,
+            projects,
+            tasks,
+            time_entries: Vec::new(),
+        }
+    }
+}
+
+/// An inclusive range of days.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateRange {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+impl DateRange {
+    pub fn day(date: NaiveDate) -> Self {
+        DateRange {
+            from: date,
+            to: date,
+        }
+    }
+
+    pub fn today() -> Self {
+        Self::day(day::today())
+    }
+
+    /// Monday to Sunday of the week containing `date`.
+    pub fn week_of(date: NaiveDate) -> Self {
+        let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+        DateRange {
+            from: monday,
+            to: monday + Duration::days(6),
+        }
+    }
+
+    pub fn this_week() -> Self {
+        Self::week_of(day::today())
+    }
+
+    pub fn last_week() -> Self {
+        Self::week_of(day::today() - Duration::days(7))
+    }
+
+    pub fn contains(&self, date: &str) -> bool {
+        date >= self.from.to_string().as_str() && date <= self.to.to_string().as_str()
+    }
+
+    pub fn file_stem(&self) -> String {
+        if self.from == self.to {
+            format!("small-worklog-{}", self.from)
+        } else {
+            format!("small-worklog-{}-to-{}", self.from, self.to)
+        }
+    }
+}
+
+/// Group tasks by the day they were created on, oldest day first.
+pub fn tasks_by_date<'a>(
+    tasks: impl IntoIterator<Item = &'a Task>,
+) -> BTreeMap<String, Vec<&'a Task>> {
+    group_by_date(tasks, Task::created_at_date)
+}
+
+/// Group anything dated by its `YYYY-MM-DD` day, oldest day first.
+pub fn group_by_date<'a, T>(
+    items: impl IntoIterator<Item = &'a T>,
+    date_of: impl Fn(&T) -> String,
+) -> BTreeMap<String, Vec<&'a T>> {
+    let mut items_by_date: BTreeMap<String, Vec<&T>> = BTreeMap::new();
+    for item in items {
+        items_by_date.entry(date_of(item)).or_default().push(item);
+    }
+    items_by_date
+}
+
+/// Group tasks by project, sorted by project name with tasks without a
+/// project first.
+pub fn tasks_by_project<'a, 'p>(
+    tasks: impl IntoIterator<Item = &'a Task>,
+    projects: &'p [Project],
+) -> Vec<(Option<&'p Project>, Vec<&'a Task>)> {
+    let mut tasks_by_project: Vec<(Option<&Project>, Vec<&Task>)> = Vec::new();
+    for task in tasks {
+        let project = task
+            .project_id
+            .as_ref()
+            .and_then(|id| projects.iter().find(|p| &p.id == id));
+        match tasks_by_project
+            .iter_mut()
+            .find(|(p, _)| p.map(|p| &p.id) == project.map(|p| &p.id))
+        {
+            Some((_, group)) => group.push(task),
+            None => tasks_by_project.push((project, vec![task])),
+        }
+    }
+    tasks_by_project.sort_by_key(|(p, _)| p.map(|p| p.name.to_lowercase()));
+    tasks_by_project
+}
+
+/// Render the tasks of a date range as Markdown, one heading per day and a
+/// checklist item per task, ready to paste into a status update. With
+/// `with_notes` each task's notes follow it, nested under its item.
+pub fn markdown_report(
+    tasks: &[Task],
+    projects: &[Project],
+    range: DateRange,
+    with_notes: bool,
+) -> String {
+    let tasks_by_date = tasks_by_date(
+        tasks
+            .iter()
+            .filter(|task| range.contains(&task.created_at_date())),
+    );
+
+    let mut out = String::new();
+    if range.from == range.to {
+        let _ = writeln!(out, "# Worklog {}", range.from);
+    } else {
+        let _ = writeln!(out, "# Worklog {} – {}", range.from, range.to);
+    }
+
+    if tasks_by_date.is_empty() {
+        let _ = writeln!(out, "\n_No tasks._");
+    }
+
+    for (date, tasks) in tasks_by_date.iter() {
+        let _ = writeln!(out, "\n## {}\n", date);
+        for task in tasks {
+            let _ = writeln!(out, "{}", markdown_item(task, projects));
+            if with_notes && !task.notes.trim().is_empty() {
+                let _ = writeln!(out, "\n{}\n", indent(&task.notes, "  "));
+            }
+        }
+    }
+    out
+}
+
+fn markdown_item(task: &Task, projects: &[Project]) -> String {
+    let mut line = format!(
+        "- [{}] {}",
+        if task.completed { "x" } else { " " },
+        task.description.trim_end()
+    );
+    if let Some(project) = task
+        .project_id
+        .as_ref()
+        .and_then(|id| projects.iter().find(|p| &p.id == id))
+    {
+        let _ = write!(line, " @{}", project.name);
+    }
+    for tag in task.tags.iter() {
+        let _ = write!(line, " #{}", tag);
+    }
+    if task.completed {
+        if let Some(completed_at) = DateTime::from_timestamp(task.completed_at as i64, 0) {
+            let _ = write!(
+                line,
+                " _(done {})_",
+                completed_at.with_timezone(&Local).format("%H:%M")
+            );
+        }
+    }
+    line
+}
+
+/// Prefix every line of `text` but blank ones with `prefix`.
+pub fn indent(text: &str, prefix: &str) -> String {
+    text.trim_end()
+        .lines()
+        .map(|line| match line.trim_end() {
+            "" => String::new(),
+            line => format!("{}{}", prefix, line),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// All tasks as CSV, one row per task. Projects are referenced by name, tags
+/// are separated by spaces, the priority is its level from 0 to 4 and unset
+/// values are left empty.
+pub fn tasks_csv(tasks: &[Task], projects: &[Project]) -> String {
+    let mut out = CSV_HEADER.join(",");
+    out.push_str("\r\n");
+    for task in tasks {
+        let project = task
+            .project_id
+            .as_ref()
+            .and_then(|id| projects.iter().find(|p| &p.id == id))
+            .map(|p| p.name.as_str())
+            .unwrap_or_default();
+        let row = [
+            task.id.clone(),
+            task.description.clone(),
+            task.completed.to_string(),
+            task.created_at.to_string(),
+            task.created_at_date.clone(),
+            task.completed_at.to_string(),
+            project.to_owned(),
+            task.tags.join(" "),
+            task.priority.level().to_string(),
+            task.due_at.map(|t| t.to_string()).unwrap_or_default(),
+            task.recurrence
+                .as_ref()
+                .map(|r| r.to_string())
+                .unwrap_or_default(),
+            task.parent_id.clone().unwrap_or_default(),
+            task.notes.clone(),
+            task.deleted_at.map(|t| t.to_string()).unwrap_or_default(),
+        ];
+        let row: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+        out.push_str(&row.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
@@ -1,35 +1,140 @@
 use eframe::egui::{self, Color32, Ui};
-use std::{
-    collections::HashMap,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
+use std::collections::HashSet;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
 };
+use std::time::{Duration, Instant};
 
 use crate::{
+    config::{self, Config},
+    day,
+    export::{self, DateRange},
+    markdown,
     store::{self, Store},
-    types::Task,
+    timesheet::{GroupBy, Timesheet},
+    types::{self, Priority, Project, SearchHit, Task, TimeEntry},
+    widgets,
+    worklog::SharedWorklog,
 };
 
-pub struct AppFrame {
-    show_deferred_history: Arc<AtomicBool>,
-    store: store::Store,
+/// How long an error stays in the banner unless dismissed earlier.
+const ERROR_DISPLAY_TIME: Duration = Duration::from_secs(8);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExportRange {
+    Today,
+    ThisWeek,
+    LastWeek,
+}
+
+impl ExportRange {
+    fn label(&self) -> &'static str {
+        match self {
+            ExportRange::Today => "Today",
+            ExportRange::ThisWeek => "This week",
+            ExportRange::LastWeek => "Last week",
+        }
+    }
+
+    fn date_range(&self) -> DateRange {
+        match self {
+            ExportRange::Today => DateRange::today(),
+            ExportRange::ThisWeek => DateRange::this_week(),
+            ExportRange::LastWeek => DateRange::last_week(),
+        }
+    }
 }
 
-impl Default for AppFrame {
+/// State of the history viewport, shared with its deferred closure.
+struct HistoryState {
+    tag_filter: Option<String>,
+    priority_view: crate::widgets::PriorityView,
+    /// The task being edited in place and its description buffer.
+    editing: Option<(String, String)>,
+    export_range: ExportRange,
+    export_status: Option<String>,
+    timesheet_by_tag: bool,
+    /// Whether Markdown exports include the notes of each task.
+    export_notes: bool,
+    /// Whether the notes are shown under each task.
+    show_notes: bool,
+    search: String,
+    search_hits: Vec<SearchHit>,
+    /// The query and worklog revision `search_hits` belong to.
+    searched: Option<(String, u64)>,
+    /// The search result picked last, scrolled to once and then highlighted.
+    found_task: Option<String>,
+    scroll_to_found: bool,
+    /// Tasks whose subtasks are hidden.
+    collapsed: HashSet<String>,
+}
+
+impl Default for HistoryState {
     fn default() -> Self {
-        let store = Store::default().unwrap();
         Self {
-            show_deferred_history: Arc::new(AtomicBool::new(false)),
-            store,
+            tag_filter: None,
+            priority_view: Default::default(),
+            editing: None,
+            export_range: ExportRange::ThisWeek,
+            export_status: None,
+            timesheet_by_tag: false,
+            export_notes: false,
+            show_notes: false,
+            search: String::new(),
+            search_hits: Vec::new(),
+            searched: None,
+            found_task: None,
+            scroll_to_found: false,
+            collapsed: HashSet::new(),
         }
     }
 }
 
+pub struct AppFrame {
+    show_deferred_history: Arc<AtomicBool>,
+    history: Arc<Mutex<HistoryState>>,
+    current_project: Option<String>,
+    show_projects: bool,
+    show_trash: bool,
+    new_project_name: String,
+    /// The project being renamed and the name typed so far, saved on Enter
+    /// or when the field loses focus.
+    renaming_project: Option<(String, String)>,
+    config: Config,
+    new_profile_name: String,
+    worklog: SharedWorklog,
+    /// The worklog revision the history viewport last saw.
+    history_revision: u64,
+    /// Errors shown in the banner below the title bar, with when they happened.
+    errors: Vec<(String, Instant)>,
+}
+
 impl AppFrame {
+    pub fn new(worklog: SharedWorklog) -> Self {
+        Self {
+            show_deferred_history: Arc::new(AtomicBool::new(false)),
+            history: Arc::new(Mutex::new(HistoryState::default())),
+            current_project: None,
+            show_projects: false,
+            show_trash: false,
+            new_project_name: String::new(),
+            renaming_project: None,
+            config: Config::load(),
+            new_profile_name: String::new(),
+            worklog,
+            history_revision: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    /// The project picked in the title bar, `None` meaning all projects.
+    pub fn current_project(&self) -> Option<String> {
+        self.current_project.clone()
+    }
+
     pub fn window(
-        &self,
+        &mut self,
         ctx: &egui::Context,
         title: &str,
         add_contents: impl FnOnce(&mut egui::Ui),
@@ -44,6 +149,25 @@ impl AppFrame {
             ..Default::default()
         };
 
+        let (projects, revision, read_only, in_memory) = {
+            let mut worklog = self.worklog.lock().unwrap();
+            let now = Instant::now();
+            self.errors
+                .extend(worklog.take_errors().into_iter().map(|err| (err, now)));
+            (
+                worklog.projects(),
+                worklog.revision(),
+                worklog.is_read_only(),
+                worklog.is_in_memory(),
+            )
+        };
+        self.errors
+            .retain(|(_, at)| at.elapsed() < ERROR_DISPLAY_TIME);
+        if let Some((_, at)) = self.errors.first() {
+            ctx.request_repaint_after(ERROR_DISPLAY_TIME.saturating_sub(at.elapsed()));
+        }
+        let projects = projects.as_slice();
+
         CentralPanel::default().frame(panel_frame).show(ctx, |ui| {
             let app_rect = ui.max_rect();
 
@@ -53,7 +177,7 @@ impl AppFrame {
                 rect.max.y = rect.min.y + title_bar_height;
                 rect
             };
-            AppFrame::title_bar_ui(self, ui, title_bar_rect, title);
+            AppFrame::title_bar_ui(self, ui, title_bar_rect, title, projects);
 
             // Add the contents:
             let content_rect = {
@@ -63,15 +187,31 @@ impl AppFrame {
             }
             .shrink(4.0);
             let mut content_ui = ui.child_ui(content_rect, *ui.layout());
+            self.error_banner(&mut content_ui, read_only, in_memory);
             add_contents(&mut content_ui);
         });
 
+        if self.show_projects {
+            self.projects_window(ctx, projects);
+        }
+        if self.show_trash {
+            self.trash_window(ctx);
+        }
+
         // open the history viewport
         if self.show_deferred_history.load(Ordering::Relaxed) {
             let show_deferred_viewport = self.show_deferred_history.clone();
-            let todo_list: Vec<Task> = self.store.get_all_tasks().unwrap_or_default();
+            let history = self.history.clone();
+            let worklog = self.worklog.clone();
+
+            // let the history viewport know the worklog changed
+            if revision != self.history_revision {
+                self.history_revision = revision;
+                ctx.request_repaint_of(history_viewport_id());
+            }
+
             ctx.show_viewport_deferred(
-                egui::ViewportId::from_hash_of("deferred_history_viewport"),
+                history_viewport_id(),
                 egui::ViewportBuilder::default()
                     .with_title("Worklog History")
                     .with_inner_size([400.0, 500.0]),
@@ -85,10 +225,45 @@ impl AppFrame {
                         show_deferred_viewport.store(false, Ordering::Relaxed);
                     }
 
+                    let (todo_list, projects, time_entries, revision) = {
+                        let worklog = worklog.lock().unwrap();
+                        (
+                            worklog.tasks(),
+                            worklog.projects(),
+                            worklog.time_entries(),
+                            worklog.revision(),
+                        )
+                    };
+                    let mut history = history.lock().unwrap();
+                    egui::TopBottomPanel::top("history_menu").show(ctx, |ui| {
+                        egui::menu::bar(ui, |ui| {
+                            ui_export_menu(
+                                ui,
+                                &mut history,
+                                &worklog,
+                                &todo_list,
+                                &projects,
+                                &time_entries,
+                            );
+                            crate::widgets::priority_menu(ui, &mut history.priority_view);
+                            ui.checkbox(&mut history.show_notes, "Notes")
+                                .on_hover_text("Show the notes under each task");
+                        });
+                    });
+
                     // show history
                     egui::CentralPanel::default().show(ctx, |ui| {
+                        ui_search(ui, &todo_list, &worklog, revision, &mut history);
+                        crate::widgets::tag_filter_bar(ui, &mut history.tag_filter);
                         egui::ScrollArea::vertical().show(ui, |ui| {
-                            ui_history(ui, &todo_list);
+                            ui_history(
+                                ui,
+                                &todo_list,
+                                &projects,
+                                &time_entries,
+                                &worklog,
+                                &mut history,
+                            );
                         });
                     });
                 },
@@ -96,7 +271,13 @@ impl AppFrame {
         }
     }
 
-    fn title_bar_ui(&self, ui: &mut egui::Ui, title_bar_rect: eframe::epaint::Rect, title: &str) {
+    fn title_bar_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        title_bar_rect: eframe::epaint::Rect,
+        title: &str,
+        projects: &[Project],
+    ) {
         use egui::*;
 
         let painter = ui.painter();
@@ -129,10 +310,388 @@ impl AppFrame {
                 ui.visuals_mut().button_frame = false;
                 ui.add_space(8.0);
                 AppFrame::close_menu(self, ui);
+                ui.add_space(8.0);
+                AppFrame::settings_menu(self, ui);
+                ui.add_space(8.0);
+                AppFrame::project_selector(self, ui, projects);
+                ui.add_space(8.0);
+                AppFrame::profile_selector(self, ui);
+                ui.add_space(8.0);
+                AppFrame::running_timer(self, ui);
             });
         });
     }
 
+    fn settings_menu(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("⚙", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Day starts at");
+                let mut hour = day::day_start_hour();
+                let response = ui.add(
+                    egui::DragValue::new(&mut hour)
+                        .clamp_range(0..=23)
+                        .custom_formatter(|hour, _| format!("{:02}:00", hour)),
+                );
+                if response.changed() {
+                    day::set_day_start_hour(hour);
+                    let mut worklog = self.worklog.lock().unwrap();
+                    let result = worklog.set_setting(day::DAY_START_SETTING, &hour.to_string());
+                    worklog.report(result);
+                }
+            })
+            .response
+            .on_hover_text("Work done before this hour counts towards the previous day");
+
+            ui.horizontal(|ui| {
+                ui.label("Keep trash for");
+                let mut worklog = self.worklog.lock().unwrap();
+                let days = worklog.get_setting(store::TRASH_RETENTION_SETTING);
+                let mut days = worklog
+                    .report(days)
+                    .flatten()
+                    .and_then(|days| days.parse().ok())
+                    .unwrap_or(store::DEFAULT_TRASH_RETENTION_DAYS);
+                let response = ui.add(
+                    egui::DragValue::new(&mut days)
+                        .clamp_range(0..=3650)
+                        .custom_formatter(|days, _| match days as u32 {
+                            0 => "ever".to_owned(),
+                            1 => "1 day".to_owned(),
+                            days => format!("{} days", days),
+                        }),
+                );
+                if response.changed() {
+                    let result =
+                        worklog.set_setting(store::TRASH_RETENTION_SETTING, &days.to_string());
+                    worklog.report(result);
+                }
+            })
+            .response
+            .on_hover_text("Trashed tasks are deleted for good after this long, on the next start");
+
+            let mut worklog = self.worklog.lock().unwrap();
+            let mut auto_complete = worklog.auto_complete_parent();
+            let response = ui
+                .checkbox(&mut auto_complete, "Complete tasks with their last subtask")
+                .on_hover_text("Reopening a subtask reopens the task again");
+            if response.changed() {
+                let value = if auto_complete { "true" } else { "false" };
+                let result = worklog.set_setting(store::AUTO_COMPLETE_PARENT_SETTING, value);
+                worklog.report(result);
+            }
+            let mut render_markdown = worklog.render_markdown();
+            let response = ui
+                .checkbox(&mut render_markdown, "Format descriptions as Markdown")
+                .on_hover_text("Turn off to see descriptions as typed, like `**bold**`");
+            if response.changed() {
+                let result = worklog.set_render_markdown(render_markdown);
+                worklog.report(result);
+            }
+            drop(worklog);
+
+            if ui.button("Trash…").clicked() {
+                self.show_trash = true;
+                ui.close_menu();
+            }
+        });
+    }
+
+    /// The task being timed and for how long, click to stop the timer.
+    fn running_timer(&mut self, ui: &mut egui::Ui) {
+        let mut worklog = self.worklog.lock().unwrap();
+        let Some(entry) = worklog.running_timer() else {
+            return;
+        };
+        let description = worklog
+            .tasks()
+            .iter()
+            .find(|t| t.id == entry.task_id)
+            .map(|t| t.description.trim_end().to_owned())
+            .unwrap_or_default();
+        let text = egui::RichText::new(format!(
+            "⏸ {}",
+            types::format_duration(entry.seconds(), true)
+        ))
+        .color(ui.visuals().warn_fg_color);
+
+        if ui
+            .button(text)
+            .on_hover_text(format!("Stop timing: {}", description))
+            .clicked()
+        {
+            let result = worklog.stop_timer();
+            worklog.report(result);
+        }
+    }
+
+    /// Pick the project new tasks go to and the main list is limited to.
+    fn project_selector(&mut self, ui: &mut egui::Ui, projects: &[Project]) {
+        let selected_text = projects
+            .iter()
+            .find(|p| Some(&p.id) == self.current_project.as_ref())
+            .map(|p| format!("● {}", p.name))
+            .unwrap_or_else(|| "All projects".to_owned());
+
+        egui::ComboBox::from_id_source("project_selector")
+            .selected_text(selected_text)
+            .width(120.0)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.current_project, None, "All projects");
+                for project in projects.iter().filter(|p| !p.archived) {
+                    let text = egui::RichText::new(format!("● {}", project.name))
+                        .color(widgets::project_color(project));
+                    ui.selectable_value(&mut self.current_project, Some(project.id.clone()), text);
+                }
+                ui.separator();
+                if ui.button("Manage projects…").clicked() {
+                    self.show_projects = true;
+                }
+            });
+    }
+
+    /// Switch between the databases listed in the config file.
+    fn profile_selector(&mut self, ui: &mut egui::Ui) {
+        if config::database_overridden() {
+            ui.label(egui::RichText::new("custom db").small().weak())
+                .on_hover_text(config::database_path().display().to_string());
+            return;
+        }
+
+        let selected_text = self
+            .config
+            .active_profile
+            .clone()
+            .unwrap_or_else(|| "Default".to_owned());
+        let mut selected = self.config.active_profile.clone();
+
+        egui::ComboBox::from_id_source("profile_selector")
+            .selected_text(selected_text)
+            .width(80.0)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut selected, None, "Default")
+                    .on_hover_text(self.config.database_path.as_ref().map_or_else(
+                        || config::default_database_path().display().to_string(),
+                        |path| path.display().to_string(),
+                    ));
+                for (name, path) in self.config.profiles.iter() {
+                    ui.selectable_value(&mut selected, Some(name.clone()), name)
+                        .on_hover_text(path.display().to_string());
+                }
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.new_profile_name)
+                            .hint_text("new profile")
+                            .desired_width(100.0),
+                    );
+                    let name = self.new_profile_name.trim().to_owned();
+                    if ui.button("Add").clicked() && !name.is_empty() {
+                        self.config.add_profile(&name);
+                        selected = Some(name);
+                        self.new_profile_name.clear();
+                    }
+                });
+            });
+
+        if selected != self.config.active_profile {
+            self.switch_profile(selected);
+        }
+    }
+
+    /// Point the shared worklog at the database of another profile.
+    fn switch_profile(&mut self, profile: Option<String>) {
+        let previous = self.config.active_profile.clone();
+        self.config.active_profile = profile;
+        let mut worklog = self.worklog.lock().unwrap();
+        let opened = Store::open(&self.config.database_path()).and_then(|store| {
+            day::set_day_start_hour(store.day_start_hour()?);
+            Ok(store)
+        });
+        match opened {
+            Ok(store) => {
+                let result = worklog.reopen(store);
+                if worklog.report(result).is_some() {
+                    self.current_project = None;
+                }
+                if let Err(err) = self.config.save() {
+                    eprintln!("failed to save config: {}", err);
+                }
+            }
+            Err(err) => {
+                worklog.report_error(err);
+                self.config.active_profile = previous;
+            }
+        }
+    }
+
+    /// Create, rename, recolor and archive projects.
+    fn projects_window(&mut self, ctx: &egui::Context, projects: &[Project]) {
+        let mut open = self.show_projects;
+        egui::Window::new("Projects")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                for project in projects.iter() {
+                    let mut project = project.clone();
+                    let mut changed = false;
+                    ui.horizontal(|ui| {
+                        let mut rgb = project.rgb();
+                        if ui.color_edit_button_srgb(&mut rgb).changed() {
+                            project.set_rgb(rgb);
+                            changed = true;
+                        }
+                        match &mut self.renaming_project {
+                            Some((id, name)) if *id == project.id => {
+                                let response =
+                                    ui.add(egui::TextEdit::singleline(name).desired_width(140.0));
+                                if response.lost_focus() {
+                                    let name = name.trim().to_owned();
+                                    self.renaming_project = None;
+                                    let cancelled = ui.input(|i| i.key_pressed(egui::Key::Escape));
+                                    if !cancelled && !name.is_empty() && name != project.name {
+                                        project.name = name;
+                                        changed = true;
+                                    }
+                                }
+                            }
+                            _ => {
+                                // same widget id as above, so focus carries over
+                                let mut name = project.name.clone();
+                                let response = ui.add(
+                                    egui::TextEdit::singleline(&mut name).desired_width(140.0),
+                                );
+                                if response.gained_focus() || response.changed() {
+                                    self.renaming_project = Some((project.id.clone(), name));
+                                }
+                            }
+                        }
+                        if ui.checkbox(&mut project.archived, "archived").changed() {
+                            changed = true;
+                            if project.archived && self.current_project == Some(project.id.clone())
+                            {
+                                self.current_project = None;
+                            }
+                        }
+                    });
+                    if changed {
+                        // a rename clashing with another project is rejected by the store
+                        let mut worklog = self.worklog.lock().unwrap();
+                        let result = worklog.update_project(&project);
+                        worklog.report(result);
+                    }
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.new_project_name)
+                            .hint_text("new project")
+                            .desired_width(140.0),
+                    );
+                    let name = self.new_project_name.trim().to_owned();
+                    if ui.button("Add").clicked() && !name.is_empty() {
+                        let mut worklog = self.worklog.lock().unwrap();
+                        let project = worklog.find_or_create_project(&name);
+                        if let Some(project) = worklog.report(project) {
+                            self.current_project = Some(project.id);
+                        }
+                        self.new_project_name.clear();
+                    }
+                });
+            });
+        self.show_projects = open;
+    }
+
+    /// Recent errors, each dismissable, and a permanent notice while the
+    /// database is read-only or could not be opened at all.
+    fn error_banner(&mut self, ui: &mut egui::Ui, read_only: bool, in_memory: bool) {
+        let error_color = ui.visuals().error_fg_color;
+        if in_memory {
+            ui.label(
+                egui::RichText::new(
+                    "⚠ The database could not be opened, changes are lost when the app closes",
+                )
+                .color(error_color),
+            );
+        }
+        if read_only {
+            ui.label(
+                egui::RichText::new("⚠ The database is read-only, changes cannot be saved")
+                    .color(ui.visuals().warn_fg_color),
+            );
+        }
+
+        let mut dismissed = None;
+        for (index, (err, _)) in self.errors.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.small_button("❌").on_hover_text("Dismiss").clicked() {
+                    dismissed = Some(index);
+                }
+                ui.label(egui::RichText::new(err).color(error_color));
+            });
+        }
+        if let Some(index) = dismissed {
+            self.errors.remove(index);
+        }
+    }
+
+    /// Deleted tasks, to restore or delete for good.
+    fn trash_window(&mut self, ctx: &egui::Context) {
+        let trash = self.worklog.lock().unwrap().trash();
+        let mut open = self.show_trash;
+        egui::Window::new("Trash")
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                if trash.is_empty() {
+                    ui.label(egui::RichText::new("The trash is empty").weak());
+                    return;
+                }
+
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for task in trash.iter() {
+                            ui.horizontal(|ui| {
+                                if ui.small_button("Restore").clicked() {
+                                    let mut worklog = self.worklog.lock().unwrap();
+                                    let result = worklog.restore_task(&task.id);
+                                    worklog.report(result);
+                                }
+                                if ui
+                                    .small_button("🗑")
+                                    .on_hover_text("Delete for good")
+                                    .clicked()
+                                {
+                                    let mut worklog = self.worklog.lock().unwrap();
+                                    let result = worklog.purge_task(&task.id);
+                                    worklog.report(result);
+                                }
+                                ui.label(task.description.trim_end());
+                                if let Some(deleted_at) = task.deleted_at {
+                                    ui.label(
+                                        egui::RichText::new(day::day_of(deleted_at).to_string())
+                                            .small()
+                                            .weak(),
+                                    )
+                                    .on_hover_text("Deleted on");
+                                }
+                            });
+                        }
+                    });
+
+                ui.separator();
+                if ui.button("Empty trash").clicked() {
+                    let mut worklog = self.worklog.lock().unwrap();
+                    let result = worklog.empty_trash();
+                    worklog.report(result);
+                }
+            });
+        self.show_trash = open;
+    }
+
     /// Show some close/maximize/minimize buttons for the native window.
     fn close_menu(&self, ui: &mut egui::Ui) {
         use egui::{Button, RichText};
@@ -155,45 +714,379 @@ impl AppFrame {
     }
 }
 
-fn ui_history(ui: &mut Ui, tasks: &[Task]) {
-    // Group tasks by created_at_date
-    let mut tasks_by_date: HashMap<String, Vec<&Task>> = HashMap::new();
-    for task in tasks {
-        let date = task.created_at_date();
-        tasks_by_date.entry(date).or_default().push(task);
+fn history_viewport_id() -> egui::ViewportId {
+    egui::ViewportId::from_hash_of("deferred_history_viewport")
+}
+
+/// Export a range of days as Markdown, to the clipboard or to a file.
+fn ui_export_menu(
+    ui: &mut Ui,
+    history: &mut HistoryState,
+    worklog: &SharedWorklog,
+    tasks: &[Task],
+    projects: &[Project],
+    time_entries: &[TimeEntry],
+) {
+    ui.menu_button("Export", |ui| {
+        for range in [
+            ExportRange::Today,
+            ExportRange::ThisWeek,
+            ExportRange::LastWeek,
+        ] {
+            ui.radio_value(&mut history.export_range, range, range.label());
+        }
+        ui.checkbox(&mut history.export_notes, "Include notes");
+        ui.separator();
+
+        let range = history.export_range.date_range();
+        let with_notes = history.export_notes;
+        if ui.button("Copy Markdown").clicked() {
+            let markdown = export::markdown_report(tasks, projects, range, with_notes);
+            ui.ctx().output_mut(|o| o.copied_text = markdown);
+            history.export_status = Some("Copied to clipboard".to_owned());
+            ui.close_menu();
+        }
+        if ui.button("Save Markdown file").clicked() {
+            let markdown = export::markdown_report(tasks, projects, range, with_notes);
+            history.export_status =
+                Some(save_export(&format!("{}.md", range.file_stem()), markdown));
+            ui.close_menu();
+        }
+
+        ui.separator();
+        ui.label("Timesheet");
+        ui.checkbox(&mut history.timesheet_by_tag, "Group by tag")
+            .on_hover_text("Group by each task's first tag instead of by project");
+        let save_csv = ui.button("Save timesheet CSV").clicked();
+        let save_html = ui
+            .button("Save timesheet HTML")
+            .on_hover_text("Hourly rates and rounding come from the config file")
+            .clicked();
+        if save_csv || save_html {
+            let group_by = if history.timesheet_by_tag {
+                GroupBy::Tag
+            } else {
+                GroupBy::Project
+            };
+            let timesheet = Timesheet::new(
+                tasks,
+                projects,
+                time_entries,
+                range,
+                group_by,
+                &Config::load().timesheet,
+            );
+            history.export_status = Some(if save_html {
+                save_export(
+                    &format!("{}.html", timesheet.file_stem()),
+                    timesheet.to_html(),
+                )
+            } else {
+                save_export(
+                    &format!("{}.csv", timesheet.file_stem()),
+                    timesheet.to_csv(),
+                )
+            });
+            ui.close_menu();
+        }
+
+        ui.separator();
+        ui.label("Whole database");
+        let save_json = ui
+            .button("Save JSON backup")
+            .on_hover_text("Everything, including tracked time and the trash")
+            .clicked();
+        let save_csv = ui
+            .button("Save CSV")
+            .on_hover_text("All tasks, including the trash")
+            .clicked();
+        if save_json || save_csv {
+            let backup = worklog.lock().unwrap().backup();
+            history.export_status = Some(match backup {
+                Ok(backup) if save_csv => save_export(
+                    "small-worklog-tasks.csv",
+                    export::tasks_csv(&backup.tasks, &backup.projects),
+                ),
+                Ok(backup) => match serde_json::to_string_pretty(&backup) {
+                    Ok(json) => save_export("small-worklog-backup.json", json),
+                    Err(err) => format!("Failed to export: {}", err),
+                },
+                Err(err) => format!("Failed to export: {}", err),
+            });
+            ui.close_menu();
+        }
+    });
+
+    if let Some(status) = &history.export_status {
+        ui.label(egui::RichText::new(status).small().weak());
     }
+}
 
-    // Sort dates
-    let mut sorted_dates: Vec<_> = tasks_by_date.keys().collect();
-    sorted_dates.sort_by(|a, b| b.cmp(a));
+/// Write an export to the documents folder, returning a status message.
+fn save_export(file_name: &str, content: String) -> String {
+    let mut path = dirs::document_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_default();
+    path.push(file_name);
+    match std::fs::write(&path, content) {
+        Ok(()) => format!("Saved to {}", path.display()),
+        Err(err) => format!("Failed to save {}: {}", path.display(), err),
+    }
+}
 
-    // Begin the UI layout
-    ui.vertical_centered(|ui| {
-        // Iterate through each date with tasks
-        for dates in &sorted_dates {
-            if let Some(tasks) = tasks_by_date.get(*dates) {
-                // Add a header for the date
+/// The search box and its results. Picking a result scrolls the history to
+/// the task and highlights it.
+fn ui_search(
+    ui: &mut Ui,
+    tasks: &[Task],
+    worklog: &SharedWorklog,
+    revision: u64,
+    history: &mut HistoryState,
+) {
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut history.search)
+                .hint_text("🔍 Search, \"a phrase\" or prefix*")
+                .desired_width(ui.available_width() - 24.0),
+        );
+        if !history.search.is_empty() && ui.small_button("❌").clicked() {
+            history.search.clear();
+            history.found_task = None;
+        }
+    });
+
+    // search again when the query or the tasks changed, edits and deletes included
+    let key = (history.search.clone(), revision);
+    if history.searched.as_ref() != Some(&key) {
+        let mut worklog = worklog.lock().unwrap();
+        let hits = worklog.search(&history.search);
+        history.search_hits = worklog.report(hits).unwrap_or_default();
+        history.searched = Some(key);
+    }
+
+    if history.search.trim().is_empty() {
+        return;
+    }
+    if history.search_hits.is_empty() {
+        ui.label(egui::RichText::new("No matches").weak());
+        return;
+    }
+
+    egui::ScrollArea::vertical()
+        .id_source("search_results")
+        .max_height(160.0)
+        .show(ui, |ui| {
+            for hit in history.search_hits.iter() {
+                let Some(task) = tasks.iter().find(|t| t.id == hit.task_id) else {
+                    continue;
+                };
                 ui.horizontal(|ui| {
-                    ui.label(egui::RichText::new(*dates).color(Color32::DARK_GREEN));
+                    let date = ui
+                        .small_button(task.created_at_date())
+                        .on_hover_text("Show in history");
+                    if date.clicked() {
+                        history.found_task = Some(task.id.clone());
+                        history.scroll_to_found = true;
+                        // make sure the task is not filtered out or folded away
+                        history.tag_filter = None;
+                        history.priority_view.at_least = Priority::None;
+                        if let Some(parent_id) = &task.parent_id {
+                            history.collapsed.remove(parent_id);
+                        }
+                    }
+                    widgets::highlighted_label(ui, hit.highlighted.trim_end());
                 });
+            }
+        });
+    ui.separator();
+}
+
+fn ui_history(
+    ui: &mut Ui,
+    tasks: &[Task],
+    projects: &[Project],
+    time_entries: &[TimeEntry],
+    worklog: &SharedWorklog,
+    history: &mut HistoryState,
+) {
+    let HistoryState {
+        tag_filter,
+        priority_view,
+        editing,
+        found_task,
+        scroll_to_found,
+        collapsed,
+        show_notes,
+        ..
+    } = history;
+    let subtask_progress = types::subtask_progress(tasks);
+    let tracked_by_task = types::tracked_seconds(time_entries);
+    let tracked_by_day = types::tracked_by_day(time_entries);
+    let render_markdown = worklog.lock().unwrap().render_markdown();
+
+    // Group tasks by created_at_date
+    let tasks_by_date = export::tasks_by_date(
+        tasks
+            .iter()
+            .filter(|task| tag_filter.as_ref().map_or(true, |tag| task.has_tag(tag)))
+            .filter(|task| priority_view.matches(task)),
+    );
 
-                // Begin a table for tasks
-                ui.vertical(|ui| {
-                    // Add a row for each task
-                    for task in tasks.iter() {
+    // Begin the UI layout
+    ui.vertical_centered(|ui| {
+        // Iterate through each date with tasks, newest first
+        for (dates, tasks) in tasks_by_date.iter().rev() {
+            // Add a header for the date, with the time tracked that day
+            let header = ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(dates).color(Color32::DARK_GREEN));
+                let tracked = dates
+                    .parse()
+                    .ok()
+                    .and_then(|day| tracked_by_day.get(&day))
+                    .copied()
+                    .unwrap_or_default();
+                if tracked > 0 {
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "⏱ {}",
+                            types::format_duration(tracked, false)
+                        ))
+                        .weak(),
+                    )
+                    .on_hover_text("Time tracked on this day");
+                }
+            });
+            if *scroll_to_found && tasks.iter().any(|t| Some(&t.id) == found_task.as_ref()) {
+                header.response.scroll_to_me(Some(egui::Align::TOP));
+                *scroll_to_found = false;
+            }
+
+            // Group the day's tasks by project, tasks without a project first
+            let mut tasks_by_project = export::tasks_by_project(tasks.iter().copied(), projects);
+            for (_, tasks) in tasks_by_project.iter_mut() {
+                priority_view.sort(tasks);
+            }
+
+            // Begin a table for tasks
+            ui.vertical(|ui| {
+                for (project, tasks) in tasks_by_project.iter() {
+                    if let Some(project) = project {
+                        widgets::project_label(ui, project);
+                    }
+                    // Add a row for each task, subtasks under their parent
+                    for (task, nested) in types::nest(tasks) {
+                        let hidden = task
+                            .parent_id
+                            .as_ref()
+                            .is_some_and(|id| collapsed.contains(id));
+                        if nested && hidden {
+                            continue;
+                        }
                         ui.horizontal(|ui| {
+                            if nested {
+                                ui.add_space(20.0);
+                            }
+                            let progress = subtask_progress.get(task.id.as_str()).copied();
+                            if progress.is_some() {
+                                let expanded = !collapsed.contains(&task.id);
+                                let icon = if expanded { "⏷" } else { "⏵" };
+                                if ui
+                                    .add(egui::Button::new(icon).small().frame(false))
+                                    .clicked()
+                                {
+                                    if expanded {
+                                        collapsed.insert(task.id.clone());
+                                    } else {
+                                        collapsed.remove(&task.id);
+                                    }
+                                }
+                            }
                             let is_completed = if task.completed {
                                 "\u{2714}"
                             } else {
                                 "\u{2795}"
                             };
-                            ui.label(format!("{} {}", is_completed, task.description).trim_end());
+                            ui.label(is_completed);
+                            let mut priority = task.priority;
+                            if widgets::priority_marker(ui, &mut priority) {
+                                let mut task = (*task).clone();
+                                task.priority = priority;
+                                let mut worklog = worklog.lock().unwrap();
+                                let result = worklog.update_task(&task);
+                                worklog.report(result);
+                                ui.ctx().request_repaint_of(egui::ViewportId::ROOT);
+                            }
+
+                            if let Some((_, buffer)) =
+                                editing.as_mut().filter(|(id, _)| id == &task.id)
+                            {
+                                match widgets::inline_editor(ui, buffer) {
+                                    widgets::EditAction::Commit => {
+                                        let description = buffer.trim().to_owned();
+                                        if !description.is_empty() {
+                                            let mut task = (*task).clone();
+                                            task.description = description;
+                                            let mut worklog = worklog.lock().unwrap();
+                                            let result = worklog.update_task(&task);
+                                            worklog.report(result);
+                                            ui.ctx().request_repaint_of(egui::ViewportId::ROOT);
+                                        }
+                                        *editing = None;
+                                    }
+                                    widgets::EditAction::Cancel => *editing = None,
+                                    widgets::EditAction::Continue => {}
+                                }
+                                return;
+                            }
+
+                            let background = (found_task.as_ref() == Some(&task.id))
+                                .then_some(ui.visuals().selection.bg_fill);
+                            let description = widgets::description_label(
+                                ui,
+                                task.description.trim_end(),
+                                background,
+                                !render_markdown,
+                            );
+                            if description.double_clicked() {
+                                *editing =
+                                    Some((task.id.clone(), task.description.trim_end().to_owned()));
+                            }
+                            if let Some((done, total)) = progress {
+                                widgets::subtask_progress(ui, done, total);
+                            }
+                            if !*show_notes && !task.notes.trim().is_empty() {
+                                ui.label("📝")
+                                    .on_hover_ui(|ui| markdown::show(ui, &task.notes));
+                            }
+                            for tag in task.tags.iter() {
+                                let selected = tag_filter.as_ref() == Some(tag);
+                                if widgets::tag_chip(ui, tag, selected).clicked() {
+                                    widgets::toggle_tag_filter(tag_filter, tag);
+                                }
+                            }
+                            let tracked = tracked_by_task
+                                .get(task.id.as_str())
+                                .copied()
+                                .unwrap_or_default();
+                            if tracked > 0 {
+                                ui.label(
+                                    egui::RichText::new(types::format_duration(tracked, false))
+                                        .weak(),
+                                )
+                                .on_hover_text("Total time tracked on this task");
+                            }
                         });
+                        if *show_notes && !task.notes.trim().is_empty() {
+                            ui.horizontal(|ui| {
+                                ui.add_space(if nested { 44.0 } else { 24.0 });
+                                markdown::show(ui, &task.notes);
+                            });
+                        }
                     }
-                    ui.separator();
-                });
-                ui.add_space(12.0);
-            }
+                }
+                ui.separator();
+            });
+            ui.add_space(12.0);
         }
     });
 }
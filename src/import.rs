@@ -0,0 +1,453 @@
+use crate::error::Result;
+use crate::export::{Backup, BACKUP_VERSION, CSV_HEADER};
+use crate::recurrence::Recurrence;
+use crate::store::Store;
+use crate::types::{Priority, Project, Task};
+use chrono::{DateTime, NaiveDate};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read};
+use std::path::Path;
+
+/// A task that was not imported because the database already has a
+/// different task with the same id.
+#[derive(Serialize, Debug, Clone)]
+pub struct Conflict {
+    pub id: String,
+    pub existing: String,
+    pub incoming: String,
+}
+
+/// What an import did, or would do in a dry run.
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub added: usize,
+    pub unchanged: usize,
+    pub projects_added: usize,
+    pub time_entries_added: usize,
+    pub conflicts: Vec<Conflict>,
+    pub invalid: Vec<String>,
+}
+
+/// Read a backup file, picking the format from the extension. Besides the
+/// versioned JSON written by `export`, the bare task array written by v1 of
+/// the app and CSV exports are accepted.
+pub fn load_file(path: &Path) -> Result<Backup, Error> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+    let is_csv = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    if is_csv {
+        read_csv(reader)
+    } else {
+        read_json(reader)
+    }
+}
+
+pub fn read_json(reader: impl Read) -> Result<Backup, Error> {
+    let value: serde_json::Value = serde_json::from_reader(reader)?;
+    if value.is_array() {
+        let tasks: Vec<Task> = serde_json::from_value(value)?;
+        return Ok(Backup::new(tasks, Vec::new()));
+    }
+
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+    if version > BACKUP_VERSION as u64 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "backup version {} is newer than supported version {}",
+                version, BACKUP_VERSION
+            ),
+        ));
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// How many columns CSV exports had before they included every task field.
+const OLD_CSV_COLUMNS: usize = 8;
+
+pub fn read_csv(mut reader: impl Read) -> Result<Backup, Error> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+    let mut rows = parse_csv(&content).into_iter();
+
+    let header = rows.next().unwrap_or_default();
+    if header != CSV_HEADER && header != CSV_HEADER[..OLD_CSV_COLUMNS] {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("unexpected csv header, expected {}", CSV_HEADER.join(",")),
+        ));
+    }
+
+    let invalid = |line: usize, field: &str| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid {} on csv row {}", field, line),
+        )
+    };
+
+    let mut projects: Vec<Project> = Vec::new();
+    let mut tasks = Vec::new();
+    for (index, row) in rows.enumerate() {
+        let line = index + 2;
+        if row.len() != header.len() {
+            return Err(invalid(line, "column count"));
+        }
+        // the columns old exports lack, `None` when missing or empty
+        let optional = |index: usize, field: &str| -> Result<Option<u64>, Error> {
+            match row.get(index).map(String::as_str) {
+                None | Some("") => Ok(None),
+                Some(value) => value.parse().map(Some).map_err(|_| invalid(line, field)),
+            }
+        };
+        let recurrence = match row.get(10).map(String::as_str) {
+            None | Some("") => None,
+            Some(rule) => Some(
+                rule.parse::<Recurrence>()
+                    .map_err(|_| invalid(line, "recurrence"))?,
+            ),
+        };
+
+        let project_id = match row[6].as_str() {
+            "" => None,
+            name => {
+                let existing = projects.iter().find(|p| p.name.eq_ignore_ascii_case(name));
+                let project = match existing {
+                    Some(project) => project.clone(),
+                    None => {
+                        let project = Project::new(name.to_owned());
+                        projects.push(project.clone());
+                        project
+                    }
+                };
+                Some(project.id)
+            }
+        };
+
+        tasks.push(Task {
+            id: row[0].clone(),
+            description: row[1].clone(),
+            completed: row[2].parse().map_err(|_| invalid(line, "completed"))?,
+            created_at: row[3].parse().map_err(|_| invalid(line, "created_at"))?,
+            created_at_date: row[4].clone(),
+            completed_at: row[5].parse().map_err(|_| invalid(line, "completed_at"))?,
+            tags: row[7].split_whitespace().map(str::to_owned).collect(),
+            project_id,
+            position: 0,
+            priority: Priority::from_level(optional(8, "priority")?.unwrap_or_default() as i64),
+            due_at: optional(9, "due_at")?,
+            recurrence,
+            parent_id: row.get(11).filter(|id| !id.is_empty()).cloned(),
+            notes: row.get(12).cloned().unwrap_or_default(),
+            deleted_at: optional(13, "deleted_at")?,
+        });
+    }
+    Ok(Backup::new(tasks, projects))
+}
+
+/// Split CSV text into rows of fields, honouring quoted fields that contain
+/// commas, quotes or line breaks. Blank lines are skipped.
+fn parse_csv(content: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => in_quotes = false,
+            ('"', false) if field.is_empty() => in_quotes = true,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) if chars.peek() == Some(&'\n') => {}
+            ('\n', false) if row.is_empty() && field.is_empty() => {}
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Why a task cannot be imported, if it cannot. `has_task` tells whether the
+/// database has a task, to check subtasks against.
+fn validate(task: &Task, has_task: impl Fn(&str) -> bool) -> Option<String> {
+    if task.id.trim().is_empty() {
+        return Some(format!("task `{}` has no id", task.description.trim_end()));
+    }
+    if task.description.trim().is_empty() {
+        return Some(format!("task {} has an empty description", task.id));
+    }
+    if DateTime::from_timestamp(task.created_at as i64, 0).is_none() || task.created_at == 0 {
+        return Some(format!("task {} has an invalid created_at", task.id));
+    }
+    if NaiveDate::parse_from_str(&task.created_at_date, "%Y-%m-%d").is_err() {
+        return Some(format!("task {} has an invalid created_at_date", task.id));
+    }
+    if let Some(parent_id) = task.parent_id.as_deref().filter(|id| !has_task(id)) {
+        return Some(format!(
+            "task {} has an unknown parent {}",
+            task.id, parent_id
+        ));
+    }
+    None
+}
+
+/// Whether two versions of a task only differ in their place in the list,
+/// which backups of other databases and CSV files do not carry over.
+fn same_task(a: &Task, b: &Task) -> bool {
+    Task {
+        position: b.position,
+        ..a.clone()
+    } == *b
+}
+
+/// Merge a backup into the store. Tasks are deduplicated by id: identical
+/// tasks are skipped and differing ones are reported as conflicts, keeping
+/// the existing row. Projects are matched by id, then by name. Time entries
+/// are added unless already present or their task is unknown. A dry run
+/// does all the work inside a transaction that is rolled back.
+pub fn import(store: &Store, backup: Backup, dry_run: bool) -> Result<ImportReport> {
+    store.in_transaction(!dry_run, |store| {
+        let mut report = ImportReport {
+            dry_run,
+            ..Default::default()
+        };
+
+        let existing_projects = store.get_all_projects()?;
+        let mut project_ids: Vec<(String, String)> = Vec::new();
+        for project in backup.projects.iter() {
+            let existing = existing_projects
+                .iter()
+                .find(|p| p.id == project.id)
+                .or_else(|| {
+                    existing_projects
+                        .iter()
+                        .find(|p| p.name.eq_ignore_ascii_case(&project.name))
+                });
+            match existing {
+                Some(existing) => project_ids.push((project.id.clone(), existing.id.clone())),
+                None => {
+                    store.add_project(project)?;
+                    report.projects_added += 1;
+                    project_ids.push((project.id.clone(), project.id.clone()));
+                }
+            }
+        }
+
+        // parents go in before their subtasks, whatever their positions
+        let mut tasks = backup.tasks;
+        tasks.sort_by_key(|task| task.position);
+        let (parents, subtasks): (Vec<Task>, Vec<Task>) =
+            tasks.into_iter().partition(|task| task.parent_id.is_none());
+        for mut task in parents.into_iter().chain(subtasks) {
+            let has_task = |id: &str| matches!(store.get_task(id), Ok(Some(_)));
+            if let Some(reason) = validate(&task, has_task) {
+                report.invalid.push(reason);
+                continue;
+            }
+            task.project_id = task.project_id.and_then(|id| {
+                project_ids
+                    .iter()
+                    .find(|(from, _)| *from == id)
+                    .map(|(_, to)| to.clone())
+                    .or_else(|| {
+                        existing_projects
+                            .iter()
+                            .find(|p| p.id == id)
+                            .map(|p| p.id.clone())
+                    })
+            });
+
+            match store.get_task(&task.id)? {
+                Some(existing) if same_task(&existing, &task) => report.unchanged += 1,
+                Some(existing) => report.conflicts.push(Conflict {
+                    id: task.id.clone(),
+                    existing: existing.description,
+                    incoming: task.description,
+                }),
+                None => {
+                    // after the existing tasks, in the order of the backup
+                    task.position = store.next_position()?;
+                    store.add_task(&task)?;
+                    report.added += 1;
+                }
+            }
+        }
+
+        let existing_entries = store.backup()?.time_entries;
+        for entry in backup.time_entries {
+            let known = existing_entries.iter().any(|e| e.id == entry.id);
+            if !known && store.get_task(&entry.task_id)?.is_some() {
+                store.add_time_entry(&entry)?;
+                report.time_entries_added += 1;
+            }
+        }
+        Ok(report)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_subtasks_listed_before_their_parent() {
+        let parent = Task::new("ship release".to_owned());
+        let mut subtask = Task::new("tag build".to_owned());
+        subtask.parent_id = Some(parent.id.clone());
+        let mut orphan = Task::new("lost step".to_owned());
+        orphan.parent_id = Some("missing".to_owned());
+        let backup = Backup::new(vec![subtask.clone(), orphan, parent.clone()], Vec::new());
+
+        let store = Store::in_memory().unwrap();
+        let report = import(&store, backup, false).unwrap();
+        assert_eq!(report.added, 2);
+        assert_eq!(report.invalid.len(), 1, "{:?}", report.invalid);
+        assert!(report.invalid[0].contains("unknown parent"));
+        let imported = store.get_task(&subtask.id).unwrap().unwrap();
+        assert_eq!(imported.parent_id, Some(parent.id));
+    }
+
+    #[test]
+    fn splits_csv_rows_and_fields() {
+        let cases: &[(&str, &[&[&str]])] = &[
+            ("", &[]),
+            ("a,b\r\nc,d\r\n", &[&["a", "b"], &["c", "d"]]),
+            ("a,b\nc,d", &[&["a", "b"], &["c", "d"]]),
+            ("a,,\n", &[&["a", "", ""]]),
+            ("a\n\r\n\nb\n", &[&["a"], &["b"]]),
+            (
+                "\"x, y\",\"say \"\"hi\"\"\"\r\n",
+                &[&["x, y", "say \"hi\""]],
+            ),
+            ("\"two\r\nlines\",z\n", &[&["two\r\nlines", "z"]]),
+            ("\"\",b\n", &[&["", "b"]]),
+            // a quote inside an unquoted field is kept as is
+            ("a\"b\",c\n", &[&["a\"b\"", "c"]]),
+            ("\"unclosed,a\nb", &[&["unclosed,a\nb"]]),
+        ];
+        for (content, expected) in cases {
+            assert_eq!(parse_csv(content), *expected, "{content:?}");
+        }
+    }
+
+    #[test]
+    fn csv_exports_keep_every_task_field() {
+        let parent = Task::new("ship release".to_owned());
+        let mut task = Task::new("write \"notes\", then tag".to_owned());
+        task.tags = vec!["release".to_owned(), "docs".to_owned()];
+        task.priority = Priority::High;
+        task.due_at = Some(task.created_at + 3600);
+        task.recurrence = Some(Recurrence::Weekly(vec![chrono::Weekday::Fri]));
+        task.parent_id = Some(parent.id.clone());
+        task.notes = "- [ ] changelog\n- [x] version".to_owned();
+        task.deleted_at = Some(task.created_at + 60);
+        let project = Project::new("acme".to_owned());
+        task.project_id = Some(project.id.clone());
+
+        let csv = crate::export::tasks_csv(
+            &[parent.clone(), task.clone()],
+            std::slice::from_ref(&project),
+        );
+        let backup = read_csv(csv.as_bytes()).unwrap();
+        assert_eq!(backup.tasks[0], parent);
+        // projects are matched by name, so they get a new id
+        assert_eq!(backup.projects.len(), 1);
+        assert_eq!(backup.projects[0].name, project.name);
+        task.project_id = Some(backup.projects[0].id.clone());
+        assert_eq!(backup.tasks[1], task);
+    }
+
+    #[test]
+    fn reimporting_an_export_changes_nothing() {
+        let store = Store::in_memory().unwrap();
+        let project = store.find_or_create_project("acme").unwrap();
+        for description in ["first", "second"] {
+            let mut task = Task::new(description.to_owned());
+            task.position = store.next_position().unwrap();
+            task.project_id = Some(project.id.clone());
+            task.tags = vec!["b".to_owned(), "a".to_owned()];
+            store.add_task(&task).unwrap();
+        }
+        let backup = store.backup().unwrap();
+
+        let csv = crate::export::tasks_csv(&backup.tasks, &backup.projects);
+        let report = import(&store, read_csv(csv.as_bytes()).unwrap(), false).unwrap();
+        assert_eq!((report.added, report.unchanged), (0, 2), "{:?}", report);
+        let report = import(&store, backup, false).unwrap();
+        assert_eq!((report.added, report.unchanged), (0, 2), "{:?}", report);
+    }
+
+    #[test]
+    fn imported_tasks_go_after_the_existing_ones() {
+        let store = Store::in_memory().unwrap();
+        let mut existing = Task::new("existing".to_owned());
+        existing.position = store.next_position().unwrap();
+        store.add_task(&existing).unwrap();
+
+        let csv = crate::export::tasks_csv(
+            &[Task::new("one".to_owned()), Task::new("two".to_owned())],
+            &[],
+        );
+        import(&store, read_csv(csv.as_bytes()).unwrap(), false).unwrap();
+        let descriptions: Vec<String> = store
+            .get_all_tasks()
+            .unwrap()
+            .into_iter()
+            .map(|task| task.description)
+            .collect();
+        assert_eq!(descriptions, ["existing", "one", "two"]);
+    }
+
+    #[test]
+    fn reads_csv_exports_without_the_newer_columns() {
+        let csv =
+            "id,description,completed,created_at,created_at_date,completed_at,project,tags\r\n\
+                   a1,Fix login,true,1760400000,2025-10-14,1760403600,,bug urgent\r\n";
+        let backup = read_csv(csv.as_bytes()).unwrap();
+        let task = &backup.tasks[0];
+        assert_eq!(task.description, "Fix login");
+        assert_eq!(task.tags, ["bug", "urgent"]);
+        assert_eq!(task.priority, Priority::None);
+        assert_eq!((task.due_at, task.deleted_at), (None, None));
+
+        let short_row = csv.replace(",bug urgent", "");
+        assert!(read_csv(short_row.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn backups_keep_the_trash_and_its_tracked_time() {
+        let source = Store::in_memory().unwrap();
+        let task = Task::new("old spike".to_owned());
+        source.add_task(&task).unwrap();
+        let mut entry = crate::types::TimeEntry::start(task.id.clone());
+        entry.stopped_at = Some(entry.started_at + 1800);
+        source.add_time_entry(&entry).unwrap();
+        source
+            .execute(&crate::command::Command::delete(task))
+            .unwrap();
+
+        let target = Store::in_memory().unwrap();
+        let report = import(&target, source.backup().unwrap(), false).unwrap();
+        assert_eq!((report.added, report.time_entries_added), (1, 1));
+        let report = import(&target, source.backup().unwrap(), false).unwrap();
+        assert_eq!((report.unchanged, report.time_entries_added), (1, 0));
+        let trashed = target.get_trashed_tasks().unwrap();
+        assert_eq!(trashed.len(), 1);
+        assert_eq!(target.backup().unwrap().time_entries, vec![entry]);
+    }
+}
@@ -1,11 +1,25 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 mod app;
+mod cli;
+mod command;
+mod config;
+mod date_phrase;
+mod day;
+mod error;
+mod export;
 mod frame;
+mod import;
+mod markdown;
 mod migrate;
+mod quick_add;
+mod recurrence;
 mod store;
+mod timesheet;
 mod types;
 mod version;
+mod widgets;
+mod worklog;
 
 use app::MyApp;
 use eframe::egui::{self};
@@ -15,7 +29,16 @@ use version::read_version_from_toml;
 fn main() -> Result<(), eframe::Error> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
 
-    migrate::try_migrate_v1();
+    let args = match cli::take_database_option(std::env::args().skip(1).collect()) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    };
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
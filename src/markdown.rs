@@ -0,0 +1,626 @@
+//! Just enough Markdown for task notes: headings, lists, checklists, quotes,
+//! code blocks and rules, with `**strong**`, `*emphasis*`, `~~strikethrough~~`,
+//! `` `code` `` and links inside. Anything else is shown as written.
+
+use eframe::egui::{self, Color32, RichText, Ui};
+
+/// A run of text with one style, or a link.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Span {
+    pub text: String,
+    pub strong: bool,
+    pub emphasis: bool,
+    pub strikethrough: bool,
+    pub code: bool,
+    pub url: Option<String>,
+}
+
+/// A paragraph-level piece of a note.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    /// `# Title` to `###### Title`.
+    Heading(usize, String),
+    /// Lines up to the next blank line, joined by spaces.
+    Paragraph(String),
+    /// `- item`, `* item` or `1. item`, indented by `depth` levels. Checklist
+    /// items, `- [ ] item`, carry whether they are checked.
+    Item {
+        depth: usize,
+        marker: String,
+        checked: Option<bool>,
+        text: String,
+    },
+    Quote(String),
+    /// A fenced code block, without its fences.
+    Code(String),
+    Rule,
+}
+
+/// Split a note into blocks.
+pub fn blocks(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut lines = text.lines().peekable();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        if let Some(fence) = ["```", "~~~"].into_iter().find(|f| trimmed.starts_with(f)) {
+            let mut code = Vec::new();
+            for line in lines.by_ref() {
+                if line.trim_start().starts_with(fence) {
+                    break;
+                }
+                code.push(line);
+            }
+            blocks.push(Block::Code(code.join("\n")));
+        } else if let Some(heading) = heading(trimmed) {
+            blocks.push(heading);
+        } else if is_rule(trimmed) {
+            blocks.push(Block::Rule);
+        } else if let Some(item) = item(line) {
+            blocks.push(item);
+        } else if let Some(quoted) = quote(trimmed) {
+            let mut quote = vec![quoted];
+            while let Some(quoted) = lines.peek().and_then(|line| self::quote(line.trim_start())) {
+                quote.push(quoted);
+                lines.next();
+            }
+            blocks.push(Block::Quote(quote.join(" ")));
+        } else {
+            let mut paragraph = vec![trimmed];
+            while let Some(line) = lines.peek() {
+                let trimmed = line.trim_start();
+                let starts_block = trimmed.is_empty()
+                    || trimmed.starts_with("```")
+                    || trimmed.starts_with("~~~")
+                    || heading(trimmed).is_some()
+                    || is_rule(trimmed)
+                    || item(line).is_some()
+                    || quote(trimmed).is_some();
+                if starts_block {
+                    break;
+                }
+                paragraph.push(trimmed);
+                lines.next();
+            }
+            blocks.push(Block::Paragraph(paragraph.join(" ")));
+        }
+    }
+    blocks
+}
+
+fn heading(line: &str) -> Option<Block> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let title = line[level..].strip_prefix(' ')?;
+    (1..=6).contains(&level).then(|| {
+        Block::Heading(
+            level,
+            title.trim().trim_end_matches('#').trim_end().to_owned(),
+        )
+    })
+}
+
+/// `---`, `***` or `___`, spaces allowed in between.
+fn is_rule(line: &str) -> bool {
+    let marks: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3
+        && ["-", "*", "_"]
+            .iter()
+            .any(|mark| marks.replace(mark, "").is_empty())
+}
+
+fn item(line: &str) -> Option<Block> {
+    let text = line.trim_start();
+    // two spaces or a tab per level
+    let indent: usize = line[..line.len() - text.len()]
+        .chars()
+        .map(|c| if c == '\t' { 2 } else { 1 })
+        .sum();
+    let (marker, rest) = if let Some(rest) = ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|bullet| text.strip_prefix(bullet))
+    {
+        ("•".to_owned(), rest)
+    } else {
+        let digits = text.chars().take_while(char::is_ascii_digit).count();
+        let rest = text[digits..]
+            .strip_prefix(". ")
+            .or_else(|| text[digits..].strip_prefix(") "))?;
+        if digits == 0 {
+            return None;
+        }
+        (format!("{}.", &text[..digits]), rest)
+    };
+
+    let (checked, rest) = match rest.get(..4) {
+        Some("[ ] ") => (Some(false), &rest[4..]),
+        Some("[x] " | "[X] ") => (Some(true), &rest[4..]),
+        _ => (None, rest),
+    };
+    Some(Block::Item {
+        depth: indent / 2,
+        marker,
+        checked,
+        text: rest.trim().to_owned(),
+    })
+}
+
+fn quote(line: &str) -> Option<&str> {
+    line.strip_prefix('>').map(str::trim)
+}
+
+/// Split a line into styled spans. Delimiters without a partner are kept as
+/// text, and so is anything after a backslash.
+pub fn spans(text: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    push_spans(text, &Span::default(), &mut spans);
+    spans
+}
+
+fn push_spans(text: &str, style: &Span, spans: &mut Vec<Span>) {
+    let mut plain = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if let Some((inner, after, styled)) = styled_run(rest, &plain, style) {
+            push_text(&mut plain, style, spans);
+            match inner {
+                Some(inner) => push_spans(inner, &styled, spans),
+                None => spans.push(styled),
+            }
+            rest = after;
+            continue;
+        }
+        if c == '\\' {
+            if let Some(escaped) = rest[1..].chars().next().filter(char::is_ascii_punctuation) {
+                plain.push(escaped);
+                rest = &rest[1 + escaped.len_utf8()..];
+                continue;
+            }
+        }
+        plain.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    push_text(&mut plain, style, spans);
+}
+
+fn push_text(plain: &mut String, style: &Span, spans: &mut Vec<Span>) {
+    if !plain.is_empty() {
+        spans.push(Span {
+            text: std::mem::take(plain),
+            ..style.clone()
+        });
+    }
+}
+
+/// A styled run at the start of `text`: the text to parse further with the
+/// new style (`None` when the span is complete), what follows it and the
+/// style. `before` is the text just before, to tell `snake_case` from
+/// `_emphasis_`.
+fn styled_run<'a>(
+    text: &'a str,
+    before: &str,
+    style: &Span,
+) -> Option<(Option<&'a str>, &'a str, Span)> {
+    if let Some(code) = text.strip_prefix('`') {
+        let end = code.find('`')?;
+        let span = Span {
+            text: code[..end].to_owned(),
+            code: true,
+            ..style.clone()
+        };
+        return Some((None, &code[end + 1..], span));
+    }
+    if let Some(link) = text.strip_prefix('[') {
+        let (label, rest) = link.split_once("](")?;
+        let (url, after) = rest.split_once(')')?;
+        // only open what `is_url` allows, not `file:` or `javascript:`
+        if label.contains(']') || url.contains(char::is_whitespace) || !is_url(url) {
+            return None;
+        }
+        let span = Span {
+            url: Some(url.to_owned()),
+            ..style.clone()
+        };
+        return Some((Some(label), after, span));
+    }
+    if let Some(url) = text.strip_prefix('<') {
+        let (url, after) = url.split_once('>')?;
+        if !is_url(url) || url.contains(char::is_whitespace) {
+            return None;
+        }
+        let span = Span {
+            text: url.to_owned(),
+            url: Some(url.to_owned()),
+            ..style.clone()
+        };
+        return Some((None, after, span));
+    }
+    if is_url(text) && !before.ends_with(|c: char| c.is_alphanumeric()) {
+        let end = text.find(char::is_whitespace).unwrap_or(text.len());
+        // trailing punctuation ends the sentence, not the link
+        let url = text[..end].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'', '"']);
+        if !is_url(url) {
+            return None;
+        }
+        let span = Span {
+            text: url.to_owned(),
+            url: Some(url.to_owned()),
+            ..style.clone()
+        };
+        return Some((None, &text[url.len()..], span));
+    }
+
+    for delimiter in ["**", "__", "~~", "*", "_"] {
+        let Some(inner) = text.strip_prefix(delimiter) else {
+            continue;
+        };
+        // `_` only counts at word boundaries, not inside snake_case
+        let intraword = delimiter.starts_with('_') && before.ends_with(char::is_alphanumeric);
+        if intraword || inner.starts_with(char::is_whitespace) || inner.is_empty() {
+            return None;
+        }
+        let end = closing(inner, delimiter)?;
+        let mut styled = style.clone();
+        match delimiter {
+            "**" | "__" => styled.strong = true,
+            "~~" => styled.strikethrough = true,
+            _ => styled.emphasis = true,
+        }
+        return Some((Some(&inner[..end]), &inner[end + delimiter.len()..], styled));
+    }
+    None
+}
+
+/// Where `delimiter` closes in `text`: after something other than a space,
+/// and for `_` not inside a word. Runs of the delimiter's character close it
+/// when they are as long, or three long for `***both***`; a `**` run inside
+/// `*…*` belongs to a run of its own.
+fn closing(text: &str, delimiter: &str) -> Option<usize> {
+    let mark = delimiter.chars().next()?;
+    let mut from = 0;
+    while let Some(found) = text[from..].find(mark) {
+        let start = from + found;
+        let run = text[start..].chars().take_while(|c| *c == mark).count();
+        let end = start + run - delimiter.len().min(run);
+        let after = &text[start + run..];
+        let closes = (run == delimiter.len() || run == 3)
+            && start > 0
+            && !text[..start].ends_with(char::is_whitespace)
+            && !(mark == '_' && after.starts_with(char::is_alphanumeric));
+        if closes {
+            return Some(end);
+        }
+        from = start + run;
+    }
+    None
+}
+
+fn is_url(text: &str) -> bool {
+    ["https://", "http://", "mailto:"]
+        .iter()
+        .any(|scheme| text.starts_with(scheme) && text.len() > scheme.len())
+}
+
+/// Show a note.
+pub fn show(ui: &mut Ui, text: &str) {
+    ui.vertical(|ui| {
+        for block in blocks(text) {
+            match block {
+                Block::Heading(level, title) => {
+                    let size = match level {
+                        1 => 20.0,
+                        2 => 17.0,
+                        _ => 14.0,
+                    };
+                    ui.add_space(4.0);
+                    ui.label(RichText::new(title).size(size).strong());
+                }
+                Block::Paragraph(text) => {
+                    inline(ui, &text);
+                    ui.add_space(4.0);
+                }
+                Block::Item {
+                    depth,
+                    marker,
+                    checked,
+                    text,
+                } => {
+                    ui.horizontal(|ui| {
+                        ui.add_space(depth as f32 * 16.0);
+                        ui.label(marker);
+                        match checked {
+                            Some(true) => ui.label("☑"),
+                            Some(false) => ui.label("☐"),
+                            None => ui.label(""),
+                        };
+                        inline(ui, &text);
+                    });
+                }
+                Block::Quote(text) => {
+                    let quote = ui.horizontal(|ui| {
+                        ui.add_space(10.0);
+                        inline(ui, &text);
+                    });
+                    let rect = quote.response.rect;
+                    ui.painter().vline(
+                        rect.left() + 3.0,
+                        rect.y_range(),
+                        ui.visuals().widgets.noninteractive.bg_stroke,
+                    );
+                    ui.add_space(4.0);
+                }
+                Block::Code(code) => {
+                    egui::Frame::none()
+                        .fill(ui.visuals().code_bg_color)
+                        .rounding(4.0)
+                        .inner_margin(6.0)
+                        .show(ui, |ui| {
+                            ui.add(egui::Label::new(RichText::new(code).monospace()).wrap(false));
+                        });
+                    ui.add_space(4.0);
+                }
+                Block::Rule => {
+                    ui.separator();
+                }
+            }
+        }
+    });
+}
+
+/// Show one line of inline Markdown, wrapping at the edge. Links open in the
+/// browser.
+pub fn inline(ui: &mut Ui, text: &str) -> egui::Response {
+    ui.horizontal_wrapped(|ui| {
+        ui.spacing_mut().item_spacing.x = 0.0;
+        for span in spans(text) {
+            span_ui(ui, span, None, egui::Sense::hover());
+        }
+    })
+    .response
+}
+
+/// A task description with inline Markdown on one line. It responds to
+/// clicks like a label, except on its links, which open in the browser.
+/// `background` highlights it, e.g. when the task is selected.
+pub fn description(ui: &mut Ui, text: &str, background: Option<Color32>) -> egui::Response {
+    ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing.x = 0.0;
+        let mut response: Option<egui::Response> = None;
+        for span in spans(text) {
+            let link = span.url.is_some();
+            let span = span_ui(ui, span, background, egui::Sense::click());
+            if !link {
+                response = Some(match response {
+                    Some(response) => response.union(span),
+                    None => span,
+                });
+            }
+        }
+        response.unwrap_or_else(|| ui.allocate_response(egui::Vec2::ZERO, egui::Sense::click()))
+    })
+    .inner
+}
+
+fn span_ui(
+    ui: &mut Ui,
+    span: Span,
+    background: Option<Color32>,
+    sense: egui::Sense,
+) -> egui::Response {
+    let mut text = RichText::new(&span.text);
+    if let Some(background) = background {
+        text = text.background_color(background);
+    }
+    if span.strong {
+        text = text.strong();
+    }
+    if span.emphasis {
+        text = text.italics();
+    }
+    if span.strikethrough {
+        text = text.strikethrough();
+    }
+    if span.code {
+        text = text.code();
+    }
+    let Some(url) = span.url else {
+        return ui.add(egui::Label::new(text).sense(sense));
+    };
+    let link = ui.add(egui::Link::new(text)).on_hover_text(&url);
+    if link.clicked() {
+        ui.ctx().open_url(egui::OpenUrl::new_tab(&url));
+    }
+    link
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Spans in a compact form: `b`, `i`, `s` and `c` for the styles, then
+    /// the url of links in brackets, then the text.
+    fn styled(text: &str) -> Vec<String> {
+        spans(text)
+            .into_iter()
+            .map(|span| {
+                let mut styles = String::new();
+                for (on, style) in [
+                    (span.strong, 'b'),
+                    (span.emphasis, 'i'),
+                    (span.strikethrough, 's'),
+                    (span.code, 'c'),
+                ] {
+                    if on {
+                        styles.push(style);
+                    }
+                }
+                if let Some(url) = &span.url {
+                    styles.push_str(&format!("[{}]", url));
+                }
+                if styles.is_empty() {
+                    span.text
+                } else {
+                    format!("{}:{}", styles, span.text)
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn styles_spans() {
+        let cases: &[(&str, &[&str])] = &[
+            ("plain text", &["plain text"]),
+            ("*em* and **strong**", &["i:em", " and ", "b:strong"]),
+            ("an _em_ word", &["an ", "i:em", " word"]),
+            ("__strong__ too", &["b:strong", " too"]),
+            ("~~gone~~", &["s:gone"]),
+            ("***both***", &["bi:both"]),
+            ("**bold _and em_**", &["b:bold ", "bi:and em"]),
+            ("`a*b*c` stays", &["c:a*b*c", " stays"]),
+            // snake_case is not emphasis
+            ("snake_case_name", &["snake_case_name"]),
+            ("x_1 and y_2", &["x_1 and y_2"]),
+            ("_a_b", &["_a_b"]),
+            // delimiters without a partner are text
+            ("**unclosed", &["**unclosed"]),
+            ("*unclosed", &["*unclosed"]),
+            ("`unclosed code", &["`unclosed code"]),
+            ("a * b * c", &["a * b * c"]),
+            ("2 * 3", &["2 * 3"]),
+            // escapes
+            ("\\*not em\\*", &["*not em*"]),
+            ("\\`tick\\` and \\\\", &["`tick` and \\"]),
+            ("a\\b", &["a\\b"]),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(styled(text), *expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn finds_links() {
+        let cases: &[(&str, &[&str])] = &[
+            (
+                "[docs](https://docs.rs) here",
+                &["[https://docs.rs]:docs", " here"],
+            ),
+            (
+                "[**bold** link](https://x.example)",
+                &["b[https://x.example]:bold", "[https://x.example]: link"],
+            ),
+            ("[not a link] (x)", &["[not a link] (x)"]),
+            ("[relative](x)", &["[relative](x)"]),
+            (
+                "[run](javascript:alert(1))",
+                &["[run](javascript:alert(1))"],
+            ),
+            (
+                "[secrets](file:///etc/passwd)",
+                &["[secrets](file:///etc/passwd)"],
+            ),
+            ("[empty](https://)", &["[empty](https://)"]),
+            ("[spaced](a b)", &["[spaced](a b)"]),
+            (
+                "<https://a.example>",
+                &["[https://a.example]:https://a.example"],
+            ),
+            ("<not a url>", &["<not a url>"]),
+            (
+                "see https://example.com/a.",
+                &["see ", "[https://example.com/a]:https://example.com/a", "."],
+            ),
+            (
+                "(https://example.com), then",
+                &["(", "[https://example.com]:https://example.com", "), then"],
+            ),
+            (
+                "mail mailto:me@example.com!",
+                &[
+                    "mail ",
+                    "[mailto:me@example.com]:mailto:me@example.com",
+                    "!",
+                ],
+            ),
+            ("foohttps://example.com", &["foohttps://example.com"]),
+            ("https:// alone", &["https:// alone"]),
+            ("https://.", &["https://."]),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(styled(text), *expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn splits_blocks() {
+        let item = |depth, marker: &str, checked, text: &str| Block::Item {
+            depth,
+            marker: marker.to_owned(),
+            checked,
+            text: text.to_owned(),
+        };
+        let cases: Vec<(&str, Vec<Block>)> = vec![
+            ("# Title ##", vec![Block::Heading(1, "Title".to_owned())]),
+            ("###### Six", vec![Block::Heading(6, "Six".to_owned())]),
+            (
+                "#nospace\n####### seven",
+                vec![Block::Paragraph("#nospace ####### seven".to_owned())],
+            ),
+            (
+                "one\ntwo\n\nthree",
+                vec![
+                    Block::Paragraph("one two".to_owned()),
+                    Block::Paragraph("three".to_owned()),
+                ],
+            ),
+            (
+                "- [ ] todo\n- [x] done\n  * nested [X] box\n\t+ tab\n1. first\n12) second",
+                vec![
+                    item(0, "•", Some(false), "todo"),
+                    item(0, "•", Some(true), "done"),
+                    item(1, "•", None, "nested [X] box"),
+                    item(1, "•", None, "tab"),
+                    item(0, "1.", None, "first"),
+                    item(0, "12.", None, "second"),
+                ],
+            ),
+            (
+                "-not an item\n1.5 neither",
+                vec![Block::Paragraph("-not an item 1.5 neither".to_owned())],
+            ),
+            (
+                "> quoted\n> on two lines\nafter",
+                vec![
+                    Block::Quote("quoted on two lines".to_owned()),
+                    Block::Paragraph("after".to_owned()),
+                ],
+            ),
+            (
+                "---\n* * *\n___",
+                vec![Block::Rule, Block::Rule, Block::Rule],
+            ),
+            (
+                "```rust\nlet a = 1;\n\n  # not a heading\n```\nafter",
+                vec![
+                    Block::Code("let a = 1;\n\n  # not a heading".to_owned()),
+                    Block::Paragraph("after".to_owned()),
+                ],
+            ),
+            (
+                "text\n~~~\n- code\n~~~",
+                vec![
+                    Block::Paragraph("text".to_owned()),
+                    Block::Code("- code".to_owned()),
+                ],
+            ),
+            (
+                "```\nnever closed",
+                vec![Block::Code("never closed".to_owned())],
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(blocks(text), expected, "{text:?}");
+        }
+    }
+}
@@ -1,45 +1,500 @@
+use crate::error::StoreError;
+use crate::import;
 use crate::store::Store;
 use crate::types::Task;
 use dirs::desktop_dir;
-use serde_json::from_reader;
+use rusqlite::{Connection, Result, Transaction, TransactionBehavior};
 use std::fs::File;
-use std::io::{BufReader, Error, ErrorKind};
+use std::io::{BufReader, ErrorKind};
+use std::path::{Path, PathBuf};
 
 const FILE_NAME: &str = "small-worklog-tasks.dat";
-const DATABASE_FILE: &str = "small-worklog.db";
 
-pub fn try_migrate_v1() {
-    let mut db_path = dirs::home_dir().unwrap_or_default();
-    db_path.push(DATABASE_FILE);
+/// A numbered schema change. The migration at index `n` upgrades a database
+/// from `user_version = n` to `user_version = n + 1`.
+pub struct Migration {
+    pub name: &'static str,
+    pub up: fn(&Transaction) -> Result<()>,
+}
 
-    let desktop_path = desktop_dir().unwrap_or_default();
-    let v1_json_file_path = desktop_path.join(FILE_NAME);
+/// All schema migrations, in order. Never edit or reorder an entry once it has
+/// been released, always append a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        name: "create tasks table",
+        up: migration_0_initial,
+    },
+    Migration {
+        name: "create task_tags table",
+        up: migration_1_tags,
+    },
+    Migration {
+        name: "create projects table",
+        up: migration_2_projects,
+    },
+    Migration {
+        name: "create settings table",
+        up: migration_3_settings,
+    },
+    Migration {
+        name: "create time_entries table",
+        up: migration_4_time_entries,
+    },
+    Migration {
+        name: "create full-text search index",
+        up: migration_5_search,
+    },
+    Migration {
+        name: "add tasks.deleted_at for the trash",
+        up: migration_6_trash,
+    },
+    Migration {
+        name: "create operations table for undo",
+        up: migration_7_operations,
+    },
+    Migration {
+        name: "add tasks.position for manual ordering",
+        up: migration_8_position,
+    },
+    Migration {
+        name: "add tasks.priority",
+        up: migration_9_priority,
+    },
+    Migration {
+        name: "add tasks.due_at",
+        up: migration_10_due_at,
+    },
+    Migration {
+        name: "add tasks.recurrence and operations.batch",
+        up: migration_11_recurrence,
+    },
+    Migration {
+        name: "add tasks.parent_id for subtasks",
+        up: migration_12_subtasks,
+    },
+    Migration {
+        name: "add tasks.notes",
+        up: migration_13_notes,
+    },
+];
 
-    if !db_path.exists() && v1_json_file_path.exists() {
-        migrate_db_from_v1();
-    }
+/// The schema version this binary knows how to read and write.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+pub fn schema_version(connection: &Connection) -> Result<u32> {
+    connection.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Bring the database up to `SCHEMA_VERSION`, running every pending migration
+/// in its own transaction. Refuses to touch a database written by a newer binary.
+pub fn run_migrations(connection: &mut Connection) -> Result<(), StoreError> {
+    apply(connection, MIGRATIONS)
 }
 
-pub fn migrate_db_from_v1() {
-    let tasks = load_tasks().unwrap_or_default();
-    let store = Store::default().unwrap();
-    for task in tasks.iter() {
-        store.add_task(task).unwrap();
+fn apply(connection: &mut Connection, migrations: &[Migration]) -> Result<(), StoreError> {
+    let supported = migrations.len() as u32;
+    let too_new = |found| StoreError::SchemaTooNew { found, supported };
+    let current = schema_version(connection)?;
+    if current > supported {
+        return Err(too_new(current));
     }
-    println!("migrate db from v1 succeed!");
+
+    // another process may be migrating the same database, so each step
+    // takes the write lock first and only then looks at the version
+    for _ in current..supported {
+        let tx = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let version = schema_version(&tx)?;
+        if version > supported {
+            return Err(too_new(version));
+        }
+        let Some(migration) = migrations.get(version as usize) else {
+            break;
+        };
+        (migration.up)(&tx)?;
+        tx.pragma_update(None, "user_version", version + 1)?;
+        tx.commit()?;
+        eprintln!("applied migration {}: {}", version, migration.name);
+    }
+    Ok(())
+}
+
+/// Migration 0: the tasks table, which databases created before migrations
+/// were numbered already have.
+fn migration_0_initial(tx: &Transaction) -> Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS tasks (
+            id TEXT PRIMARY KEY,
+            description TEXT NOT NULL,
+            completed BOOLEAN NOT NULL,
+            created_at INTEGER NOT NULL,
+            created_at_date TEXT NOT NULL,
+            completed_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn migration_1_tags(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE task_tags (
+            task_id TEXT NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+            tag TEXT NOT NULL,
+            PRIMARY KEY (task_id, tag)
+        );
+        CREATE INDEX task_tags_tag ON task_tags(tag);",
+    )
+}
+
+fn migration_2_projects(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE projects (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            color TEXT NOT NULL,
+            archived BOOLEAN NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL
+        );
+        ALTER TABLE tasks ADD COLUMN project_id TEXT REFERENCES projects(id) ON DELETE SET NULL;
+        CREATE INDEX tasks_project_id ON tasks(project_id);",
+    )
+}
+
+fn migration_3_settings(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );",
+    )
+}
+
+fn migration_4_time_entries(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE time_entries (
+            id TEXT PRIMARY KEY,
+            task_id TEXT NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+            started_at INTEGER NOT NULL,
+            stopped_at INTEGER
+        );
+        CREATE INDEX time_entries_task_id ON time_entries(task_id);",
+    )
+}
+
+/// Task descriptions indexed for full-text search, kept in step with `tasks`
+/// by triggers. Keyed by task id rather than rowid, which `VACUUM` may renumber.
+fn migration_5_search(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE tasks_fts USING fts5(task_id UNINDEXED, description);
+        INSERT INTO tasks_fts (task_id, description) SELECT id, description FROM tasks;
+        CREATE TRIGGER tasks_fts_insert AFTER INSERT ON tasks BEGIN
+            INSERT INTO tasks_fts (task_id, description) VALUES (new.id, new.description);
+        END;
+        CREATE TRIGGER tasks_fts_update AFTER UPDATE OF id, description ON tasks BEGIN
+            DELETE FROM tasks_fts WHERE task_id = old.id;
+            INSERT INTO tasks_fts (task_id, description) VALUES (new.id, new.description);
+        END;
+        CREATE TRIGGER tasks_fts_delete AFTER DELETE ON tasks BEGIN
+            DELETE FROM tasks_fts WHERE task_id = old.id;
+        END;",
+    )
+}
+
+fn migration_6_trash(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE tasks ADD COLUMN deleted_at INTEGER;
+        CREATE INDEX tasks_deleted_at ON tasks(deleted_at);",
+    )
+}
+
+/// The undo log: each row holds a task as JSON before and after a change.
+fn migration_7_operations(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE operations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            kind TEXT NOT NULL,
+            task_id TEXT NOT NULL,
+            before TEXT,
+            after TEXT,
+            undone BOOLEAN NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL
+        );",
+    )
+}
+
+/// Existing tasks keep the order they were created in.
+fn migration_8_position(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE tasks ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+        UPDATE tasks SET position = ordered.position
+            FROM (SELECT id, ROW_NUMBER() OVER (ORDER BY created_at, rowid) AS position FROM tasks) AS ordered
+            WHERE tasks.id = ordered.id;
+        CREATE INDEX tasks_position ON tasks(position);",
+    )
+}
+
+/// 0 is no priority, up to 4 for urgent, see `Priority::level`.
+fn migration_9_priority(tx: &Transaction) -> Result<()> {
+    tx.execute_batch("ALTER TABLE tasks ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;")
+}
+
+fn migration_10_due_at(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE tasks ADD COLUMN due_at INTEGER;
+        CREATE INDEX tasks_due_at ON tasks(due_at);",
+    )
+}
+
+/// Completing a recurring task also adds its next occurrence; `batch` ties
+/// such operations together so they are undone as one.
+fn migration_11_recurrence(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE tasks ADD COLUMN recurrence TEXT;
+        ALTER TABLE operations ADD COLUMN batch INTEGER;",
+    )
+}
+
+/// Deleting a task for good deletes its subtasks too.
+fn migration_12_subtasks(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE tasks ADD COLUMN parent_id TEXT REFERENCES tasks(id) ON DELETE CASCADE;
+        CREATE INDEX tasks_parent_id ON tasks(parent_id);",
+    )
+}
+
+fn migration_13_notes(tx: &Transaction) -> Result<()> {
+    tx.execute_batch("ALTER TABLE tasks ADD COLUMN notes TEXT NOT NULL DEFAULT '';")
+}
+
+/// Where releases before the sqlite store kept their tasks, as json.
+pub fn v1_file_path() -> Option<PathBuf> {
+    desktop_dir().map(|dir| dir.join(FILE_NAME))
+}
+
+/// The tasks of the v1 json file, none if there is no such file.
+pub fn load_v1_tasks(path: &Path) -> Result<Vec<Task>, StoreError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(StoreError::V1File(path.to_owned(), err)),
+    };
+    import::read_json(BufReader::new(file))
+        .map(|backup| backup.tasks)
+        .map_err(|err| StoreError::V1File(path.to_owned(), err))
 }
 
-fn load_tasks() -> Result<Vec<Task>, Error> {
-    let desktop_path =
-        desktop_dir().ok_or(Error::new(ErrorKind::Other, "Failed to get desktop path"))?;
-    let file_path = desktop_path.join(FILE_NAME);
+/// The one-off import of the v1 tasks for users upgrading from a release
+/// that predates the sqlite store, into the database that replaced it.
+/// Returns how many tasks were added.
+pub fn import_v1(store: &Store, tasks: &[Task]) -> Result<usize, StoreError> {
+    store.in_transaction(true, |store| {
+        let mut added = 0;
+        for task in tasks {
+            if store.get_task(&task.id)?.is_none() {
+                let mut task = task.clone();
+                task.position = store.next_position()?;
+                store.add_task(&task)?;
+                added += 1;
+            }
+        }
+        Ok(added)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A database file that is removed again at the end of the test.
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "small-worklog-test-{}-{}.db",
+                std::process::id(),
+                name
+            ));
+            let _ = std::fs::remove_file(&path);
+            Fixture(path)
+        }
+
+        fn connect(&self) -> Connection {
+            Connection::open(&self.0).unwrap()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn tables(connection: &Connection) -> Vec<String> {
+        let mut stmt = connection
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")
+            .unwrap();
+        let names = stmt.query_map([], |row| row.get(0)).unwrap();
+        names.map(|name| name.unwrap()).collect()
+    }
+
+    #[test]
+    fn upgrades_a_baseline_database_keeping_its_tasks() {
+        let fixture = Fixture::new("baseline");
+        // the schema of the release before migrations were numbered
+        fixture
+            .connect()
+            .execute_batch(
+                "CREATE TABLE tasks (
+                    id TEXT PRIMARY KEY,
+                    description TEXT NOT NULL,
+                    completed BOOLEAN NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    created_at_date TEXT NOT NULL,
+                    completed_at INTEGER NOT NULL
+                );
+                INSERT INTO tasks VALUES ('a', 'write report', 1, 1700000000, '2023-11-14', 1700003600);
+                INSERT INTO tasks VALUES ('b', 'review PR', 0, 1700007200, '2023-11-14', 0);",
+            )
+            .unwrap();
+
+        let store = Store::new(&fixture.0).unwrap();
+        assert_eq!(schema_version(&fixture.connect()).unwrap(), SCHEMA_VERSION);
+
+        let tasks = store.get_all_tasks().unwrap();
+        assert_eq!(tasks.len(), 2);
+        let report = store.get_task("a").unwrap().unwrap();
+        assert_eq!(report.description, "write report");
+        assert!(report.completed);
+        assert_eq!(report.created_at, 1700000000);
+        assert_eq!(report.completed_at, 1700003600);
+        assert_eq!(report.created_at_date, "2023-11-14");
+        assert!(report.tags.is_empty() && report.notes.is_empty());
+        assert_eq!(report.deleted_at, None);
+        // the upgraded rows are searchable like new ones
+        assert_eq!(store.search_tasks("review").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn creates_a_fresh_database() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        run_migrations(&mut connection).unwrap();
+        assert_eq!(schema_version(&connection).unwrap(), SCHEMA_VERSION);
+        let tables = tables(&connection);
+        for table in ["operations", "projects", "settings", "task_tags", "tasks"] {
+            assert!(tables.iter().any(|t| t == table), "missing {}", table);
+        }
+        let tasks: i64 = connection
+            .query_row("SELECT COUNT(*) FROM tasks", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(tasks, 0);
+
+        // running again has nothing left to do
+        run_migrations(&mut connection).unwrap();
+        assert_eq!(schema_version(&connection).unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn refuses_a_newer_schema() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection
+            .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+        match run_migrations(&mut connection) {
+            Err(StoreError::SchemaTooNew { found, supported }) => {
+                assert_eq!(found, SCHEMA_VERSION + 1);
+                assert_eq!(supported, SCHEMA_VERSION);
+            }
+            other => panic!("expected SchemaTooNew, got {:?}", other),
+        }
+        assert!(tables(&connection).is_empty());
+    }
+
+    #[test]
+    fn rolls_back_a_failing_migration() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        apply(&mut connection, &MIGRATIONS[..12]).unwrap();
+        // migration 12 adds a column and then fails creating its index
+        connection
+            .execute_batch("CREATE INDEX tasks_parent_id ON tasks(created_at);")
+            .unwrap();
+
+        assert!(run_migrations(&mut connection).is_err());
+        assert_eq!(schema_version(&connection).unwrap(), 12);
+        let columns: i64 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('tasks') WHERE name = 'parent_id'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(
+            columns, 0,
+            "the column added before the failure is rolled back"
+        );
+    }
+
+    #[test]
+    fn imports_the_v1_tasks() {
+        let file = Fixture::new("v1.json");
+        // as written by releases before the sqlite store
+        std::fs::write(
+            &file.0,
+            r#"[
+                {"id": "a", "description": "write report", "completed": true,
+                 "created_at": 1700000000, "created_at_date": "2023-11-14", "completed_at": 1700003600},
+                {"id": "b", "description": "review PR", "completed": false,
+                 "created_at": 1700007200, "created_at_date": "2023-11-14", "completed_at": 0}
+            ]"#,
+        )
+        .unwrap();
+
+        let tasks = load_v1_tasks(&file.0).unwrap();
+        let store = Store::in_memory().unwrap();
+        assert_eq!(import_v1(&store, &tasks).unwrap(), 2);
+        let imported = store.get_all_tasks().unwrap();
+        let descriptions: Vec<&str> = imported.iter().map(|t| t.description.as_str()).collect();
+        assert_eq!(descriptions, ["write report", "review PR"]);
+        assert!(imported[0].completed && !imported[1].completed);
+
+        // importing again adds nothing
+        assert_eq!(import_v1(&store, &tasks).unwrap(), 0);
+    }
+
+    #[test]
+    fn a_missing_v1_file_has_no_tasks_and_a_broken_one_is_an_error() {
+        let file = Fixture::new("v1-broken.json");
+        assert!(load_v1_tasks(&file.0).unwrap().is_empty());
+
+        std::fs::write(&file.0, r#"[{"id": "a", "description": "#).unwrap();
+        match load_v1_tasks(&file.0) {
+            Err(StoreError::V1File(path, _)) => assert_eq!(path, file.0),
+            other => panic!("expected V1File, got {:?}", other),
+        }
+    }
 
-    if file_path.exists() {
-        let file = File::open(&file_path)?;
-        let reader = BufReader::new(file);
-        let tasks: Vec<Task> = from_reader(reader)?;
-        Ok(tasks)
-    } else {
-        Ok(vec![])
+    #[test]
+    fn connections_migrating_at_once_do_not_clash() {
+        for round in 0..5 {
+            let fixture = Fixture::new(&format!("concurrent-{}", round));
+            let path = fixture.0.clone();
+            let threads: Vec<_> = (0..3)
+                .map(|_| {
+                    let path = path.clone();
+                    std::thread::spawn(move || {
+                        let mut connection = Connection::open(path).unwrap();
+                        connection
+                            .busy_timeout(std::time::Duration::from_secs(10))
+                            .unwrap();
+                        run_migrations(&mut connection)
+                    })
+                })
+                .collect();
+            for thread in threads {
+                thread.join().unwrap().unwrap();
+            }
+            assert_eq!(schema_version(&fixture.connect()).unwrap(), SCHEMA_VERSION);
+        }
     }
 }
@@ -0,0 +1,219 @@
+use crate::date_phrase;
+use crate::day;
+use crate::types::Priority;
+use chrono::Local;
+
+/// The pieces of a line typed into the quick-add box, with inline tokens such as
+/// `#tag`, `@project`, `!1` and a due date like `tomorrow 3pm` pulled out of
+/// the description.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct QuickAdd {
+    pub description: String,
+    pub tags: Vec<String>,
+    pub project: Option<String>,
+    pub priority: Priority,
+    pub due_at: Option<u64>,
+    /// The words the due date was read from, e.g. to show what was understood.
+    pub due_phrase: Option<String>,
+}
+
+pub fn parse(input: &str) -> QuickAdd {
+    let mut result = QuickAdd::default();
+    let mut words = Vec::new();
+
+    for word in input.split_whitespace() {
+        if let Some(tag) = parse_tag(word) {
+            if !result.tags.contains(&tag) {
+                result.tags.push(tag);
+            }
+        } else if let Some(project) = parse_project(word) {
+            // the last @project wins
+            result.project = Some(project);
+        } else if let Some(priority) = Priority::from_token(word) {
+            // so does the last priority
+            result.priority = priority;
+        } else {
+            words.push(word);
+        }
+    }
+
+    if let Some(phrase) = date_phrase::find(&words, day::today(), Local::now().naive_local()) {
+        result.due_at = Some(match phrase.time {
+            Some(time) => day::at(phrase.date, time),
+            None => day::start_of(phrase.date),
+        });
+        result.due_phrase = Some(words.drain(phrase.words).collect::<Vec<_>>().join(" "));
+    }
+
+    result.description = words.join(" ");
+    result
+}
+
+/// Tags are case-insensitive and limited to letters, digits, `-`, `_` and `/`
+/// so that things like `#1` in "fix issue #1" are still tags but `#` alone is not.
+pub fn parse_tag(word: &str) -> Option<String> {
+    let tag = word.strip_prefix('#')?;
+    if tag.is_empty()
+        || !tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '/')
+    {
+        return None;
+    }
+    Some(tag.to_lowercase())
+}
+
+/// Project names keep their case but are matched case-insensitively by the store.
+pub fn parse_project(word: &str) -> Option<String> {
+    let project = word.strip_prefix('@')?;
+    if project.is_empty()
+        || !project
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        return None;
+    }
+    Some(project.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Input, description, tags, project, priority and date phrase.
+    type Case = (
+        &'static str,
+        &'static str,
+        &'static [&'static str],
+        Option<&'static str>,
+        Priority,
+        Option<&'static str>,
+    );
+
+    #[test]
+    fn reads_tokens() {
+        let cases: &[Case] = &[
+            ("fix login", "fix login", &[], None, Priority::None, None),
+            // tags are lowercased and kept once
+            (
+                "fix login #bug #Bug #ui",
+                "fix login",
+                &["bug", "ui"],
+                None,
+                Priority::None,
+                None,
+            ),
+            (
+                "fix issue #1",
+                "fix issue",
+                &["1"],
+                None,
+                Priority::None,
+                None,
+            ),
+            // `#` inside a word or alone is text
+            (
+                "call C# team about issue#4 #",
+                "call C# team about issue#4 #",
+                &[],
+                None,
+                Priority::None,
+                None,
+            ),
+            ("tag #a,b", "tag #a,b", &[], None, Priority::None, None),
+            // any @project is taken, whether the store knows it or not
+            (
+                "invoice @NewClient",
+                "invoice",
+                &[],
+                Some("NewClient"),
+                Priority::None,
+                None,
+            ),
+            (
+                "invoice @acme @globex",
+                "invoice",
+                &[],
+                Some("globex"),
+                Priority::None,
+                None,
+            ),
+            (
+                "mail me@example.com",
+                "mail me@example.com",
+                &[],
+                None,
+                Priority::None,
+                None,
+            ),
+            // priorities next to and inside a date phrase
+            (
+                "ship release !1 tomorrow",
+                "ship release",
+                &[],
+                None,
+                Priority::Urgent,
+                Some("tomorrow"),
+            ),
+            (
+                "ship release tomorrow !2 3pm",
+                "ship release",
+                &[],
+                None,
+                Priority::High,
+                Some("tomorrow 3pm"),
+            ),
+            (
+                "!4 !3 tidy desk",
+                "tidy desk",
+                &[],
+                None,
+                Priority::Medium,
+                None,
+            ),
+            (
+                "!5 is not a priority",
+                "!5 is not a priority",
+                &[],
+                None,
+                Priority::None,
+                None,
+            ),
+            // runs of whitespace, tabs and newlines collapse
+            (
+                "  fix \t login\n #bug  ",
+                "fix login",
+                &["bug"],
+                None,
+                Priority::None,
+                None,
+            ),
+            ("   ", "", &[], None, Priority::None, None),
+            (
+                "#bug @acme !1",
+                "",
+                &["bug"],
+                Some("acme"),
+                Priority::Urgent,
+                None,
+            ),
+        ];
+        for (input, description, tags, project, priority, due_phrase) in cases {
+            let parsed = parse(input);
+            assert_eq!(parsed.description, *description, "description of {input:?}");
+            assert_eq!(parsed.tags, *tags, "tags of {input:?}");
+            assert_eq!(parsed.project.as_deref(), *project, "project of {input:?}");
+            assert_eq!(parsed.priority, *priority, "priority of {input:?}");
+            assert_eq!(
+                parsed.due_phrase.as_deref(),
+                *due_phrase,
+                "date of {input:?}"
+            );
+            assert_eq!(
+                parsed.due_at.is_some(),
+                due_phrase.is_some(),
+                "due of {input:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! Tasks that come back. A recurring task is one occurrence that carries the
+//! rule; completing it hands the rule on to the next occurrence, and so does
+//! an occurrence that was missed once a new day begins.
+
+use crate::command::Command;
+use crate::day;
+use crate::types::Task;
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// When a task comes back, stored as a short rule like `weekly:mon,thu`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Recurrence {
+    Daily,
+    /// Monday to Friday.
+    Weekdays,
+    /// On the given days of the week, Monday first.
+    Weekly(Vec<Weekday>),
+    /// On this day of the month, or on the last day of shorter months.
+    Monthly(u32),
+    /// This many days after the previous occurrence was completed, up to
+    /// `MAX_DAYS_AFTER_COMPLETION`.
+    AfterCompletion(u32),
+}
+
+/// The longest wait an `after:N` rule can have.
+pub const MAX_DAYS_AFTER_COMPLETION: u32 = 365;
+
+impl Recurrence {
+    /// The first day after `date` the task is due again.
+    pub fn next_after(&self, date: NaiveDate) -> NaiveDate {
+        let after = |days: u32| {
+            date.checked_add_signed(Duration::days(days.into()))
+                .unwrap_or(date)
+        };
+        let mut days = (1..=7).map_while(|n| date.checked_add_signed(Duration::days(n)));
+        match self {
+            Recurrence::Daily => after(1),
+            Recurrence::Weekdays => days
+                .find(|day| day.weekday().num_days_from_monday() < 5)
+                .unwrap_or(date),
+            Recurrence::Weekly(weekdays) if weekdays.is_empty() => after(7),
+            Recurrence::Weekly(weekdays) => days
+                .find(|day| weekdays.contains(&day.weekday()))
+                .unwrap_or(date),
+            Recurrence::Monthly(day) => {
+                let this_month = day_in_month(date.year(), date.month(), *day);
+                if this_month > date {
+                    this_month
+                } else if date.month() == 12 {
+                    day_in_month(date.year() + 1, 1, *day)
+                } else {
+                    day_in_month(date.year(), date.month() + 1, *day)
+                }
+            }
+            Recurrence::AfterCompletion(days) => after((*days).clamp(1, MAX_DAYS_AFTER_COMPLETION)),
+        }
+    }
+
+    /// The day a task given this rule today is first due, `None` for rules
+    /// that only count from a completion.
+    pub fn first_due(&self, today: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Recurrence::AfterCompletion(_) => None,
+            _ => Some(self.next_after(today.pred_opt()?)),
+        }
+    }
+
+    /// Like `Every week on Mon, Thu`.
+    pub fn describe(&self) -> String {
+        match self {
+            Recurrence::Daily => "Every day".to_owned(),
+            Recurrence::Weekdays => "Every weekday".to_owned(),
+            Recurrence::Weekly(weekdays) if weekdays.is_empty() => "Every week".to_owned(),
+            Recurrence::Weekly(weekdays) => {
+                let weekdays: Vec<String> = weekdays.iter().map(|w| w.to_string()).collect();
+                format!("Every week on {}", weekdays.join(", "))
+            }
+            Recurrence::Monthly(day) => format!("Every month on day {}", day),
+            Recurrence::AfterCompletion(1) => "1 day after completion".to_owned(),
+            Recurrence::AfterCompletion(days) => format!("{} days after completion", days),
+        }
+    }
+}
+
+/// The `day`th of the month, or its last day if the month is shorter.
+fn day_in_month(year: i32, month: u32, day: u32) -> NaiveDate {
+    (1..=day.clamp(1, 31))
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .unwrap_or_default()
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Recurrence::Daily => write!(f, "daily"),
+            Recurrence::Weekdays => write!(f, "weekdays"),
+            Recurrence::Weekly(weekdays) => {
+                let weekdays: Vec<String> = weekdays
+                    .iter()
+                    .map(|w| w.to_string().to_lowercase())
+                    .collect();
+                write!(f, "weekly:{}", weekdays.join(","))
+            }
+            Recurrence::Monthly(day) => write!(f, "monthly:{}", day),
+            Recurrence::AfterCompletion(days) => write!(f, "after:{}", days),
+        }
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid recurrence rule `{}`", rule);
+        let (kind, argument) = rule.split_once(':').unwrap_or((rule, ""));
+        match kind {
+            "daily" => Ok(Recurrence::Daily),
+            "weekdays" => Ok(Recurrence::Weekdays),
+            "weekly" => {
+                let mut weekdays = argument
+                    .split(',')
+                    .filter(|w| !w.is_empty())
+                    .map(|w| w.parse::<Weekday>().map_err(|_| invalid()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                weekdays.sort_by_key(|w| w.num_days_from_monday());
+                weekdays.dedup();
+                Ok(Recurrence::Weekly(weekdays))
+            }
+            "monthly" => match argument.parse() {
+                Ok(day @ 1..=31) => Ok(Recurrence::Monthly(day)),
+                _ => Err(invalid()),
+            },
+            "after" => match argument.parse() {
+                Ok(days @ 1..=MAX_DAYS_AFTER_COMPLETION) => Ok(Recurrence::AfterCompletion(days)),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl From<Recurrence> for String {
+    fn from(recurrence: Recurrence) -> Self {
+        recurrence.to_string()
+    }
+}
+
+impl TryFrom<String> for Recurrence {
+    type Error = String;
+
+    fn try_from(rule: String) -> Result<Self, Self::Error> {
+        rule.parse()
+    }
+}
+
+/// A new occurrence of `task` due on `date`, at the time of day `task` was
+/// due at. It is created now, the day it is scheduled for is only its due
+/// date, so reports never show days that have not happened yet.
+fn occurrence(task: &Task, date: NaiveDate, position: i64) -> Task {
+    let mut next = Task::new(task.description.clone());
+    let time = match (task.due_at, task.due_day()) {
+        (Some(due_at), Some(due_day)) => due_at.saturating_sub(day::start_of(due_day)),
+        _ => 0,
+    };
+    next.due_at = Some(day::start_of(date) + time);
+    next.tags = task.tags.clone();
+    next.project_id = task.project_id.clone();
+    next.priority = task.priority;
+    next.recurrence = task.recurrence.clone();
+    next.parent_id = task.parent_id.clone();
+    next.notes = task.notes.clone();
+    next.position = position;
+    next
+}
+
+/// The commands that change `before` into `after`, run together as one undo
+/// step. Completing a recurring task adds its next occurrence at `position`,
+/// which takes the rule over.
+pub fn update(before: Task, after: Task, position: i64) -> Vec<Command> {
+    update_on(before, after, position, day::today())
+}
+
+fn update_on(before: Task, mut after: Task, position: i64, today: NaiveDate) -> Vec<Command> {
+    let recurrence = match &after.recurrence {
+        Some(recurrence) if after.completed && !before.completed => recurrence.clone(),
+        _ => return vec![Command::update(before, after)],
+    };
+
+    let from = match recurrence {
+        Recurrence::AfterCompletion(_) => today,
+        // completing early does not skip an occurrence, completing late does
+        _ => after.due_day().map_or(today, |due_day| due_day.max(today)),
+    };
+    let next = occurrence(&after, recurrence.next_after(from), position);
+    after.recurrence = None;
+    vec![Command::update(before, after), Command::add(next)]
+}
+
+/// Open occurrences that were due before `today` were missed. Each stays in
+/// the list as overdue and hands its rule on to a new occurrence due today or
+/// later, numbered from `position`. One undo step per missed occurrence.
+pub fn catch_up(tasks: &[Task], today: NaiveDate, position: i64) -> Vec<Vec<Command>> {
+    tasks
+        .iter()
+        .filter(|task| !task.completed && !task.is_trashed())
+        .filter(|task| task.due_day().is_some_and(|due_day| due_day < today))
+        .filter_map(|task| {
+            let next_day = task.recurrence.as_ref()?.first_due(today)?;
+            let mut missed = task.clone();
+            missed.recurrence = None;
+            Some((task, missed, next_day))
+        })
+        .enumerate()
+        .map(|(index, (task, missed, next_day))| {
+            let next = occurrence(task, next_day, position + index as i64);
+            vec![Command::update(task.clone(), missed), Command::add(next)]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types;
+    use chrono::NaiveTime;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, month, day).unwrap()
+    }
+
+    /// An open task with `rule`, due at 9am on the given day.
+    fn recurring(rule: &str, due: NaiveDate) -> Task {
+        let mut task = Task::new("water plants".to_owned());
+        task.recurrence = Some(rule.parse().unwrap());
+        task.due_at = Some(day::at(due, NaiveTime::from_hms_opt(9, 0, 0).unwrap()));
+        task
+    }
+
+    /// The due day of the occurrence completing `task` on `today` adds.
+    fn complete(task: &Task, today: NaiveDate) -> Option<NaiveDate> {
+        let mut done = task.clone();
+        done.set_completed(true);
+        let commands = update_on(task.clone(), done, 7, today);
+        assert_eq!(commands[0].after.as_ref()?.recurrence, None);
+        let next = commands.get(1)?.after.as_ref()?;
+        assert_eq!(next.recurrence, task.recurrence);
+        assert_eq!(next.position, 7);
+        assert!(next.created_at <= types::now(), "created in the future");
+        assert_eq!(day::time_of(next.due_at?), "09:00");
+        next.due_day()
+    }
+
+    #[test]
+    fn completing_adds_the_next_occurrence() {
+        // Wednesday 2026-10-14
+        let today = date(10, 14);
+        let cases = [
+            // completing early does not skip the occurrence that was due
+            ("weekly:mon", date(10, 19), date(10, 26)),
+            ("daily", date(10, 20), date(10, 21)),
+            // completing late skips the missed days
+            ("daily", date(10, 10), date(10, 15)),
+            ("weekdays", date(10, 9), date(10, 15)),
+            ("weekly:mon,fri", date(10, 5), date(10, 16)),
+            // counts from the completion, not the due date
+            ("after:3", date(10, 10), date(10, 17)),
+            ("after:3", date(10, 20), date(10, 17)),
+            ("monthly:14", date(10, 14), date(11, 14)),
+        ];
+        for (rule, due, next) in cases {
+            let task = recurring(rule, due);
+            assert_eq!(complete(&task, today), Some(next), "{rule} due {due}");
+        }
+    }
+
+    #[test]
+    fn monthly_on_the_31st_falls_back_to_the_end_of_short_months() {
+        let january = recurring("monthly:31", date(1, 31));
+        let february = complete(&january, date(1, 31)).unwrap();
+        assert_eq!(february, date(2, 28));
+        let march = complete(&recurring("monthly:31", february), february).unwrap();
+        assert_eq!(march, date(3, 31));
+        let april = complete(&recurring("monthly:31", march), march).unwrap();
+        assert_eq!(april, date(4, 30));
+    }
+
+    #[test]
+    fn reopening_or_editing_adds_nothing() {
+        let task = recurring("daily", date(10, 14));
+        let mut edited = task.clone();
+        edited.description = "water the plants".to_owned();
+        assert_eq!(update_on(task.clone(), edited, 1, date(10, 14)).len(), 1);
+
+        let mut done = task.clone();
+        done.set_completed(true);
+        assert_eq!(update_on(done.clone(), task, 1, date(10, 14)).len(), 1);
+    }
+
+    #[test]
+    fn catches_up_on_missed_occurrences() {
+        let today = date(10, 14);
+        let missed = recurring("weekly:fri", date(10, 9));
+        let due_today = recurring("daily", today);
+        let mut completed = recurring("daily", date(10, 1));
+        completed.set_completed(true);
+        let after_completion = recurring("after:2", date(10, 1));
+        let tasks = [missed.clone(), due_today, completed, after_completion];
+
+        let batches = catch_up(&tasks, today, 20);
+        assert_eq!(batches.len(), 1);
+        let [update, add] = batches[0].as_slice() else {
+            panic!("expected an update and an add, got {:?}", batches[0]);
+        };
+        let overdue = update.after.as_ref().unwrap();
+        assert_eq!(overdue.id, missed.id);
+        assert!(!overdue.completed);
+        assert_eq!(overdue.recurrence, None);
+        let next = add.after.as_ref().unwrap();
+        assert_eq!(next.due_day(), Some(date(10, 16)));
+        assert_eq!(next.recurrence, missed.recurrence);
+        assert_eq!(next.position, 20);
+        assert!(next.created_at <= types::now());
+    }
+
+    #[test]
+    fn parses_rules_in_range() {
+        let cases = [
+            ("daily", Some(Recurrence::Daily)),
+            (
+                "weekly:thu,mon",
+                Some(Recurrence::Weekly(vec![Weekday::Mon, Weekday::Thu])),
+            ),
+            ("monthly:31", Some(Recurrence::Monthly(31))),
+            ("monthly:0", None),
+            ("monthly:32", None),
+            ("after:1", Some(Recurrence::AfterCompletion(1))),
+            ("after:365", Some(Recurrence::AfterCompletion(365))),
+            ("after:0", None),
+            ("after:366", None),
+            ("after:4294967295", None),
+            ("after:-1", None),
+            ("yearly", None),
+        ];
+        for (rule, expected) in cases {
+            assert_eq!(rule.parse::<Recurrence>().ok(), expected, "{rule}");
+        }
+    }
+
+    #[test]
+    fn next_after_the_last_date_does_not_overflow() {
+        let rules = [
+            Recurrence::Daily,
+            Recurrence::Weekdays,
+            Recurrence::Weekly(vec![]),
+            Recurrence::Weekly(vec![Weekday::Mon]),
+            Recurrence::Monthly(31),
+            Recurrence::AfterCompletion(u32::MAX),
+        ];
+        for rule in rules {
+            rule.next_after(NaiveDate::MAX);
+        }
+        assert_eq!(
+            Recurrence::AfterCompletion(u32::MAX).next_after(NaiveDate::MIN),
+            NaiveDate::MIN + Duration::days(365)
+        );
+    }
+}
@@ -1,103 +1,937 @@
-use crate::types::Task;
-use rusqlite::{Connection, Result};
-use std::path::PathBuf;
+use crate::command::{Command, CommandKind};
+use crate::config;
+use crate::day;
+use crate::error::{Result, StoreError};
+use crate::export::Backup;
+use crate::migrate;
+use crate::types::{
+    self, Priority, Project, SearchHit, Task, TimeEntry, HIGHLIGHT_END, HIGHLIGHT_START,
+};
+use rusqlite::{Connection, DatabaseName, ErrorCode, OpenFlags, OptionalExtension, Row};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
 
-const DATABASE_FILE: &str = "small-worklog.db";
+/// How long SQLite itself waits on a locked database before giving up.
+const BUSY_TIMEOUT: Duration = Duration::from_millis(250);
+/// How many commands are kept for undo.
+const UNDO_LIMIT: i64 = 100;
+
+/// Most search results returned, best matches first.
+const SEARCH_LIMIT: usize = 100;
+
+/// How often a write is retried after SQLite gave up, with a growing pause.
+const BUSY_RETRIES: u32 = 3;
+const BUSY_BACKOFF: Duration = Duration::from_millis(100);
+
+const TASK_COLUMNS: &str =
+    "id, description, completed, created_at, created_at_date, completed_at, project_id, deleted_at, position, priority, due_at, recurrence, parent_id, notes";
+
+/// Settings key for how many days trashed tasks are kept, 0 to keep them forever.
+pub const TRASH_RETENTION_SETTING: &str = "trash_retention_days";
+pub const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+
+/// Settings key for whether completing a task's last open subtask completes
+/// the task too, on unless set to `false`.
+pub const AUTO_COMPLETE_PARENT_SETTING: &str = "auto_complete_parent";
+
+/// Settings key for whether descriptions are shown with their Markdown
+/// formatting, on unless set to `false`.
+pub const RENDER_MARKDOWN_SETTING: &str = "render_markdown";
 
 pub struct Store {
     connection: Connection,
+    read_only: Cell<bool>,
 }
 
 impl Store {
-    pub fn new(db_path: &str) -> Result<Self> {
-        let mut path = PathBuf::new();
-        path.push(db_path);
-        let connection = Connection::open(path)?;
-        connection.execute(
-            "CREATE TABLE IF NOT EXISTS tasks (
-                id TEXT PRIMARY KEY,
-                description TEXT NOT NULL,
-                completed BOOLEAN NOT NULL,
-                created_at INTEGER NOT NULL,
-                created_at_date TEXT NOT NULL,
-                completed_at INTEGER NOT NULL
-            )",
-            [],
-        )?;
-        Ok(Store { connection })
+    pub fn new(path: &Path) -> Result<Self> {
+        let mut connection = Connection::open(path)?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+        migrate::run_migrations(&mut connection)?;
+        let read_only = connection.is_readonly(DatabaseName::Main)?;
+        let store = Store {
+            connection,
+            read_only: Cell::new(read_only),
+        };
+
+        if !store.is_read_only() {
+            // not worth failing to open over, the next start tries again
+            if let Err(err) = store.purge_expired_trash() {
+                eprintln!("failed to empty expired trash: {}", err);
+            }
+        }
+        Ok(store)
     }
 
+    /// Open the database picked by `--db`, `SMALL_WORKLOG_DB` or the config file.
     pub fn default() -> Result<Self> {
-        let mut path = dirs::home_dir().unwrap_or_default();
-        path.push(DATABASE_FILE);
-        let db_path = path.to_str().expect("Invalid database path");
+        Self::open(&config::database_path())
+    }
 
-        println!("db store: {}", db_path);
+    pub fn open(path: &Path) -> Result<Self> {
+        // only the database that predates configurable paths replaces the v1
+        // file, read before creating it so that a bad file is retried next time
+        let replaces_v1 = !path.exists() && path == config::default_database_path();
+        let v1_tasks = match migrate::v1_file_path() {
+            Some(v1_path) if replaces_v1 => migrate::load_v1_tasks(&v1_path)?,
+            _ => Vec::new(),
+        };
+        let store = Self::open_file(path)?;
+        if !v1_tasks.is_empty() && !store.is_read_only() {
+            let added = migrate::import_v1(&store, &v1_tasks)?;
+            eprintln!("imported {} tasks from small-worklog v1", added);
+        }
+        Ok(store)
+    }
+
+    fn open_file(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        eprintln!("db store: {}", path.display());
 
-        Self::new(db_path) // Creates an in-memory database
+        match Self::new(path) {
+            Err(StoreError::ReadOnly) => Self::open_read_only(path),
+            Err(StoreError::Sqlite(err))
+                if err.sqlite_error_code() == Some(ErrorCode::CannotOpen) && path.exists() =>
+            {
+                Self::open_read_only(path)
+            }
+            result => result,
+        }
     }
 
-    pub fn add_task(&self, task: &Task) -> Result<()> {
-        self.connection.execute(
-            "INSERT INTO tasks (id, description, completed, created_at, created_at_date, completed_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            (&task.id, &task.description, &task.completed, &(task.created_at as i64), &task.created_at_date, &(task.completed_at as i64)),
-        )?;
-        Ok(())
+    /// Fallback for databases we cannot write to, so past logs can still be
+    /// read. Only works if the schema is already up to date.
+    fn open_read_only(path: &Path) -> Result<Self> {
+        eprintln!("db store is read-only: {}", path.display());
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        if migrate::schema_version(&connection)? != migrate::SCHEMA_VERSION {
+            return Err(StoreError::ReadOnly);
+        }
+        Ok(Store {
+            connection,
+            read_only: Cell::new(true),
+        })
     }
 
-    pub fn update_task(&self, updated_task: &Task) -> Result<()> {
-        self.connection.execute(
-            "UPDATE tasks SET description = ?1, completed = ?2, created_at = ?3, created_at_date = ?4, completed_at = ?5 WHERE id = ?6",
-            (&updated_task.description, &updated_task.completed, &(updated_task.created_at as i64), &updated_task.created_at_date, &(updated_task.completed_at as i64), &updated_task.id),
-        )?;
-        Ok(())
+    /// A throwaway database, used when no database file can be opened at all.
+    pub fn in_memory() -> Result<Self> {
+        Self::new(Path::new(":memory:"))
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.get()
     }
 
-    pub fn _get_task(&self, id: &str) -> Result<Option<Task>> {
+    /// Whether this is the throwaway database of `in_memory`.
+    pub fn is_in_memory(&self) -> bool {
+        // `path` is `None` for files whose path is not UTF-8
+        self.connection.path() == Some("")
+    }
+
+    /// Run a write in a savepoint, retrying it when the database is locked by
+    /// another connection. Writes to a read-only database fail right away and
+    /// switch the store to read-only mode.
+    fn write<T>(&self, mut f: impl FnMut(&Connection) -> rusqlite::Result<T>) -> Result<T> {
+        if self.read_only.get() {
+            return Err(StoreError::ReadOnly);
+        }
+
+        let mut attempt = 0;
+        loop {
+            self.connection.execute_batch("SAVEPOINT write")?;
+            let result = f(&self.connection);
+            let result = match result {
+                Ok(value) => self
+                    .connection
+                    .execute_batch("RELEASE write")
+                    .map(|_| value),
+                Err(err) => {
+                    let _ = self
+                        .connection
+                        .execute_batch("ROLLBACK TO write; RELEASE write");
+                    Err(err)
+                }
+            };
+
+            match result.map_err(StoreError::from) {
+                Err(StoreError::Busy) if attempt < BUSY_RETRIES => {
+                    attempt += 1;
+                    thread::sleep(BUSY_BACKOFF * attempt);
+                }
+                Err(StoreError::ReadOnly) => {
+                    self.read_only.set(true);
+                    return Err(StoreError::ReadOnly);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Changes whenever another connection commits to the database, see
+    /// <https://www.sqlite.org/pragma.html#pragma_data_version>.
+    pub fn data_version(&self) -> Result<i64> {
+        Ok(self
+            .connection
+            .query_row("PRAGMA data_version", [], |row| row.get(0))?)
+    }
+
+    pub fn add_task(&self, task: &Task) -> Result<()> {
+        self.write(|connection| {
+            connection.execute(
+                "INSERT INTO tasks (id, description, completed, created_at, created_at_date, completed_at, project_id, deleted_at, position, priority, due_at, recurrence, parent_id, notes)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                (&task.id, &task.description, &task.completed, &(task.created_at as i64), &task.created_at_date, &(task.completed_at as i64), &task.project_id, &task.deleted_at.map(|t| t as i64), &task.position, &task.priority.level(), &task.due_at.map(|t| t as i64), &task.recurrence.as_ref().map(|r| r.to_string()), &task.parent_id, &task.notes),
+            )?;
+            set_tags(connection, &task.id, &task.tags)
+        })
+    }
+
+    pub fn get_task(&self, id: &str) -> Result<Option<Task>> {
         let mut stmt = self
             .connection
-            .prepare("SELECT * FROM tasks WHERE id = ?1")?;
+            .prepare(&format!("SELECT {} FROM tasks WHERE id = ?1", TASK_COLUMNS))?;
         let mut rows = stmt.query([id])?;
         if let Some(row) = rows.next()? {
-            Ok(Some(Task {
-                id: row.get(0)?,
-                description: row.get(1)?,
-                completed: row.get(2)?,
-                created_at: row.get(3)?,
-                created_at_date: row.get(4)?,
-                completed_at: row.get(5)?,
-            }))
+            let mut task = task_from_row(row)?;
+            let mut stmt = self
+                .connection
+                .prepare("SELECT tag FROM task_tags WHERE task_id = ?1 ORDER BY rowid")?;
+            let tags = stmt.query_map([id], |row| row.get(0))?;
+            task.tags = tags.collect::<rusqlite::Result<_>>()?;
+            Ok(Some(task))
         } else {
             Ok(None)
         }
     }
 
+    /// All tasks except those in the trash, in list order.
     pub fn get_all_tasks(&self) -> Result<Vec<Task>> {
-        let mut stmt = self.connection.prepare("SELECT id, description, completed, created_at, created_at_date, completed_at FROM tasks")?;
-        let task_iter = stmt.query_map([], |row| {
-            Ok(Task {
-                id: row.get(0)?,
-                description: row.get(1)?,
-                completed: row.get(2)?,
-                created_at: row.get(3)?,
-                created_at_date: row.get(4)?,
-                completed_at: row.get(5)?,
-            })
-        })?;
+        self.query_tasks("WHERE deleted_at IS NULL ORDER BY position, created_at, id")
+    }
+
+    /// The position that puts a new task at the end of the list.
+    pub fn next_position(&self) -> Result<i64> {
+        Ok(self.connection.query_row(
+            "SELECT COALESCE(MAX(position), 0) + 1 FROM tasks",
+            [],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Store a new order, given as task ids and their positions.
+    pub fn set_positions(&self, positions: &[(String, i64)]) -> Result<()> {
+        self.write(|connection| {
+            for (id, position) in positions {
+                connection.execute(
+                    "UPDATE tasks SET position = ?1 WHERE id = ?2",
+                    (position, id),
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Trashed tasks, most recently deleted first.
+    pub fn get_trashed_tasks(&self) -> Result<Vec<Task>> {
+        self.query_tasks("WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC")
+    }
+
+    /// Everything in the database, the trash and its tracked time included,
+    /// as written to backups.
+    pub fn backup(&self) -> Result<Backup> {
+        let tasks = self.query_tasks("ORDER BY position, created_at, id")?;
+        let mut backup = Backup::new(tasks, self.get_all_projects()?);
+        backup.time_entries = self.query_time_entries("ORDER BY started_at")?;
+        Ok(backup)
+    }
+
+    fn query_tasks(&self, filter: &str) -> Result<Vec<Task>> {
+        let mut stmt = self
+            .connection
+            .prepare(&format!("SELECT {} FROM tasks {}", TASK_COLUMNS, filter))?;
+        let task_iter = stmt.query_map([], task_from_row)?;
 
+        let mut tags = self.get_all_tags()?;
         let mut tasks = Vec::new();
         for task_result in task_iter {
-            tasks.push(task_result?);
+            let mut task = task_result?;
+            task.tags = tags.remove(&task.id).unwrap_or_default();
+            tasks.push(task);
         }
         Ok(tasks)
     }
 
+    /// Apply a command and record it for undo. Anything undone before is
+    /// forgotten, like in an editor.
+    pub fn execute(&self, command: &Command) -> Result<()> {
+        self.execute_all(std::slice::from_ref(command))
+    }
+
+    /// Apply commands that belong together, e.g. completing a recurring task
+    /// and adding its next occurrence, recorded so they are undone as one.
+    pub fn execute_all(&self, commands: &[Command]) -> Result<()> {
+        let states = commands
+            .iter()
+            .map(|command| {
+                let before = command.before.as_ref().map(to_json).transpose()?;
+                let after = command.after.as_ref().map(to_json).transpose()?;
+                Ok((before, after))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.write(|connection| {
+            connection.execute("DELETE FROM operations WHERE undone = 1", [])?;
+            let mut batch: Option<i64> = None;
+            for (command, (before, after)) in commands.iter().zip(&states) {
+                put_task(connection, command.task_id(), command.after.as_ref())?;
+                connection.execute(
+                    "INSERT INTO operations (kind, task_id, before, after, created_at, batch)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    (
+                        command.kind.as_str(),
+                        command.task_id(),
+                        before,
+                        after,
+                        types::now() as i64,
+                        batch,
+                    ),
+                )?;
+                // the batch is named after its first operation
+                batch = batch.or(Some(connection.last_insert_rowid()));
+            }
+            connection.execute(
+                "DELETE FROM operations WHERE id <= (SELECT MAX(id) FROM operations) - ?1",
+                [UNDO_LIMIT],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Revert the last command, returning it, or `None` when there is nothing to undo.
+    pub fn undo(&self) -> Result<Option<Command>> {
+        self.step("WHERE undone = 0 ORDER BY id DESC", true)
+    }
+
+    /// Apply the last undone command again.
+    pub fn redo(&self) -> Result<Option<Command>> {
+        self.step("WHERE undone = 1 ORDER BY id ASC", false)
+    }
+
+    /// Undo or redo the batch of the first operation matching `filter`,
+    /// returning the batch's first command.
+    fn step(&self, filter: &str, undo: bool) -> Result<Option<Command>> {
+        let batch: Option<i64> = self
+            .connection
+            .query_row(
+                &format!(
+                    "SELECT COALESCE(batch, id) FROM operations {} LIMIT 1",
+                    filter
+                ),
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(batch) = batch else {
+            return Ok(None);
+        };
+        // undo the batch back to front, redo it front to back
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT id, kind, before, after FROM operations
+             WHERE COALESCE(batch, id) = ?1 AND undone = ?2 ORDER BY id {}",
+            if undo { "DESC" } else { "ASC" }
+        ))?;
+        let operations = stmt
+            .query_map((batch, !undo), |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut commands = Vec::new();
+        for (id, kind, before, after) in operations {
+            // the kind is only informative, an unknown one still undoes fine
+            let command = Command {
+                kind: CommandKind::parse(&kind).unwrap_or(CommandKind::Update),
+                before: before.as_deref().map(from_json).transpose()?,
+                after: after.as_deref().map(from_json).transpose()?,
+            };
+            commands.push((id, command));
+        }
+
+        self.write(|connection| {
+            for (id, command) in commands.iter() {
+                let state = if undo {
+                    command.before.as_ref()
+                } else {
+                    command.after.as_ref()
+                };
+                put_task(connection, command.task_id(), state)?;
+                connection.execute(
+                    "UPDATE operations SET undone = ?1 WHERE id = ?2",
+                    (undo, id),
+                )?;
+            }
+            Ok(())
+        })?;
+        Ok(commands
+            .into_iter()
+            .min_by_key(|(id, _)| *id)
+            .map(|(_, command)| command))
+    }
+
+    /// Subtasks trashed together with the task come back with it.
+    pub fn restore_task(&self, id: &str) -> Result<()> {
+        self.write(|connection| {
+            connection.execute(
+                "UPDATE tasks SET deleted_at = NULL
+                 WHERE parent_id = ?1 AND deleted_at = (SELECT deleted_at FROM tasks WHERE id = ?1)",
+                [id],
+            )?;
+            connection.execute("UPDATE tasks SET deleted_at = NULL WHERE id = ?1", [id])?;
+            Ok(())
+        })
+    }
+
+    /// Permanently delete tasks trashed longer ago than the retention
+    /// setting allows, returning how many were removed.
+    pub fn purge_expired_trash(&self) -> Result<usize> {
+        let days = self
+            .get_setting(TRASH_RETENTION_SETTING)?
+            .and_then(|days| days.parse().ok())
+            .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS);
+        if days == 0 {
+            return Ok(0);
+        }
+        let cutoff = types::now().saturating_sub(days as u64 * 24 * 60 * 60);
+        self.write(|connection| {
+            connection.execute(
+                "DELETE FROM tasks WHERE deleted_at IS NOT NULL AND deleted_at < ?1",
+                [cutoff as i64],
+            )
+        })
+    }
+
+    /// Permanently delete every task in the trash.
+    pub fn empty_trash(&self) -> Result<usize> {
+        self.write(|connection| {
+            connection.execute("DELETE FROM tasks WHERE deleted_at IS NOT NULL", [])
+        })
+    }
+
+    /// Permanently delete a task, skipping the trash.
     pub fn delete_task_by_id(&self, id: &str) -> Result<()> {
-        self.connection
-            .execute("DELETE FROM tasks WHERE id = ?1", [id])?;
-        Ok(())
+        self.write(|connection| {
+            connection.execute("DELETE FROM tasks WHERE id = ?1", [id])?;
+            Ok(())
+        })
+    }
+
+    /// Run `f` inside a transaction, committing only when it succeeds and
+    /// `commit` is set. Used for bulk writes and for dry runs of them.
+    pub fn in_transaction<T>(&self, commit: bool, f: impl FnOnce(&Self) -> Result<T>) -> Result<T> {
+        let tx = self.connection.unchecked_transaction()?;
+        let result = f(self)?;
+        if commit {
+            tx.commit()?;
+        }
+        Ok(result)
+    }
+
+    pub fn add_project(&self, project: &Project) -> Result<()> {
+        self.write(|connection| {
+            connection.execute(
+                "INSERT INTO projects (id, name, color, archived, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                (&project.id, &project.name, &project.color, &project.archived, &(project.created_at as i64)),
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Persist a project's name, color and archived flag.
+    pub fn update_project(&self, project: &Project) -> Result<()> {
+        self.write(|connection| {
+            connection.execute(
+                "UPDATE projects SET name = ?1, color = ?2, archived = ?3 WHERE id = ?4",
+                (
+                    &project.name,
+                    &project.color,
+                    &project.archived,
+                    &project.id,
+                ),
+            )?;
+            Ok(())
+        })
+    }
+
+    pub fn get_all_projects(&self) -> Result<Vec<Project>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT id, name, color, archived, created_at FROM projects ORDER BY name COLLATE NOCASE",
+        )?;
+        let project_iter = stmt.query_map([], project_from_row)?;
+        Ok(project_iter.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Look a project up by name, ignoring case, creating it when it does not exist yet.
+    /// Archived projects are brought back when referenced again.
+    pub fn find_or_create_project(&self, name: &str) -> Result<Project> {
+        let existing = self
+            .connection
+            .prepare("SELECT id, name, color, archived, created_at FROM projects WHERE name = ?1 COLLATE NOCASE")?
+            .query_map([name], project_from_row)?
+            .next()
+            .transpose()?;
+
+        match existing {
+            Some(mut project) => {
+                if project.archived {
+                    project.archived = false;
+                    self.update_project(&project)?;
+                }
+                Ok(project)
+            }
+            None => {
+                let project = Project::new(name.to_owned());
+                self.add_project(&project)?;
+                Ok(project)
+            }
+        }
+    }
+
+    pub fn auto_complete_parent(&self) -> Result<bool> {
+        Ok(self.get_setting(AUTO_COMPLETE_PARENT_SETTING)?.as_deref() != Some("false"))
+    }
+
+    pub fn render_markdown(&self) -> Result<bool> {
+        Ok(self.get_setting(RENDER_MARKDOWN_SETTING)?.as_deref() != Some("false"))
+    }
+
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT value FROM settings WHERE key = ?1")?;
+        let mut rows = stmt.query([key])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The hour days start at in this database, to pass to
+    /// `day::set_day_start_hour` when opening it.
+    pub fn day_start_hour(&self) -> Result<u32> {
+        let hour = self.get_setting(day::DAY_START_SETTING)?;
+        Ok(hour.and_then(|hour| hour.parse().ok()).unwrap_or(0))
+    }
+
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        self.write(|connection| {
+            connection.execute(
+                "INSERT INTO settings (key, value) VALUES (?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                (key, value),
+            )?;
+            Ok(())
+        })
+    }
+
+    pub fn add_time_entry(&self, entry: &TimeEntry) -> Result<()> {
+        self.write(|connection| {
+            connection.execute(
+                "INSERT INTO time_entries (id, task_id, started_at, stopped_at) VALUES (?1, ?2, ?3, ?4)",
+                (
+                    &entry.id,
+                    &entry.task_id,
+                    &(entry.started_at as i64),
+                    &entry.stopped_at.map(|t| t as i64),
+                ),
+            )?;
+            Ok(())
+        })
+    }
+
+    pub fn update_time_entry(&self, entry: &TimeEntry) -> Result<()> {
+        self.write(|connection| {
+            connection.execute(
+                "UPDATE time_entries SET started_at = ?1, stopped_at = ?2 WHERE id = ?3",
+                (
+                    &(entry.started_at as i64),
+                    &entry.stopped_at.map(|t| t as i64),
+                    &entry.id,
+                ),
+            )?;
+            Ok(())
+        })
+    }
+
+    /// All time entries of tasks not in the trash, oldest first.
+    pub fn get_all_time_entries(&self) -> Result<Vec<TimeEntry>> {
+        self.query_time_entries(
+            "WHERE task_id IN (SELECT id FROM tasks WHERE deleted_at IS NULL) ORDER BY started_at",
+        )
+    }
+
+    fn query_time_entries(&self, filter: &str) -> Result<Vec<TimeEntry>> {
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT id, task_id, started_at, stopped_at FROM time_entries {}",
+            filter
+        ))?;
+        let entry_iter = stmt.query_map([], |row| {
+            Ok(TimeEntry {
+                id: row.get(0)?,
+                task_id: row.get(1)?,
+                started_at: row.get(2)?,
+                stopped_at: row.get(3)?,
+            })
+        })?;
+        Ok(entry_iter.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Full-text search over task descriptions. Words match as prefixes with
+    /// a trailing `*`, `"quoted words"` match as a phrase, and every term has
+    /// to match.
+    pub fn search_tasks(&self, query: &str) -> Result<Vec<SearchHit>> {
+        let Some(query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+        let mut stmt = self.connection.prepare(
+            "SELECT task_id, highlight(tasks_fts, 1, ?2, ?3) FROM tasks_fts
+             WHERE tasks_fts MATCH ?1
+               AND task_id IN (SELECT id FROM tasks WHERE deleted_at IS NULL)
+             ORDER BY rank LIMIT ?4",
+        )?;
+        let hits = stmt.query_map(
+            (
+                query,
+                HIGHLIGHT_START.to_string(),
+                HIGHLIGHT_END.to_string(),
+                SEARCH_LIMIT,
+            ),
+            |row| {
+                Ok(SearchHit {
+                    task_id: row.get(0)?,
+                    highlighted: row.get(1)?,
+                })
+            },
+        )?;
+        Ok(hits.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn get_all_tags(&self) -> Result<HashMap<String, Vec<String>>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT task_id, tag FROM task_tags ORDER BY rowid")?;
+        let mut rows = stmt.query([])?;
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        while let Some(row) = rows.next()? {
+            tags.entry(row.get(0)?).or_default().push(row.get(1)?);
+        }
+        Ok(tags)
     }
 
     // Add more methods as needed
 }
+
+/// Turn what the user typed into an FTS5 query that cannot be a syntax error:
+/// every word and phrase is quoted, keeping a trailing `*` for prefix matches.
+fn fts_query(input: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    let mut rest = input.trim();
+    while !rest.is_empty() {
+        let (term, remainder) = if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+        let (term, remainder, prefix) = if let Some(remainder) = remainder.strip_prefix('*') {
+            (term, remainder, true)
+        } else if let Some(term) = term.strip_suffix('*') {
+            (term, remainder, true)
+        } else {
+            (term, remainder, false)
+        };
+        rest = remainder.trim_start();
+
+        let term = term.replace('"', "");
+        if term.chars().any(char::is_alphanumeric) {
+            terms.push(format!("\"{}\"{}", term, if prefix { "*" } else { "" }));
+        }
+    }
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Make the stored task match `task`, moving it to the trash for `None` so
+/// that its tags, tracked time and subtasks are still there when an undone
+/// add is redone. The position is left alone for existing tasks, undo is not
+/// meant to revert reordering. A task that ends up completed or in the trash
+/// stops its timer.
+fn put_task(connection: &Connection, id: &str, task: Option<&Task>) -> rusqlite::Result<()> {
+    if task.map_or(true, |task| task.completed || task.is_trashed()) {
+        connection.execute(
+            "UPDATE time_entries SET stopped_at = ?2 WHERE task_id = ?1 AND stopped_at IS NULL",
+            (id, types::now() as i64),
+        )?;
+    }
+    let Some(task) = task else {
+        connection.execute(
+            "UPDATE tasks SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+            (id, types::now() as i64),
+        )?;
+        return Ok(());
+    };
+    connection.execute(
+        "INSERT INTO tasks (id, description, completed, created_at, created_at_date, completed_at, project_id, deleted_at, position, priority, due_at, recurrence, parent_id, notes)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+         ON CONFLICT(id) DO UPDATE SET description = excluded.description, completed = excluded.completed,
+             created_at = excluded.created_at, created_at_date = excluded.created_at_date,
+             completed_at = excluded.completed_at, project_id = excluded.project_id, deleted_at = excluded.deleted_at,
+             priority = excluded.priority, due_at = excluded.due_at, recurrence = excluded.recurrence,
+             parent_id = excluded.parent_id, notes = excluded.notes",
+        (&task.id, &task.description, &task.completed, &(task.created_at as i64), &task.created_at_date, &(task.completed_at as i64), &task.project_id, &task.deleted_at.map(|t| t as i64), &task.position, &task.priority.level(), &task.due_at.map(|t| t as i64), &task.recurrence.as_ref().map(|r| r.to_string()), &task.parent_id, &task.notes),
+    )?;
+    set_tags(connection, &task.id, &task.tags)
+}
+
+fn to_json(task: &Task) -> Result<String> {
+    serde_json::to_string(task)
+        .map_err(|err| StoreError::Sqlite(rusqlite::Error::ToSqlConversionFailure(Box::new(err))))
+}
+
+fn from_json(json: &str) -> Result<Task> {
+    serde_json::from_str(json).map_err(|err| {
+        StoreError::Sqlite(rusqlite::Error::FromSqlConversionFailure(
+            0,
+            rusqlite::types::Type::Text,
+            Box::new(err),
+        ))
+    })
+}
+
+fn set_tags(connection: &Connection, task_id: &str, tags: &[String]) -> rusqlite::Result<()> {
+    connection.execute("DELETE FROM task_tags WHERE task_id = ?1", [task_id])?;
+    for tag in tags {
+        connection.execute(
+            "INSERT OR IGNORE INTO task_tags (task_id, tag) VALUES (?1, ?2)",
+            (task_id, tag),
+        )?;
+    }
+    Ok(())
+}
+
+fn task_from_row(row: &Row) -> rusqlite::Result<Task> {
+    Ok(Task {
+        id: row.get(0)?,
+        description: row.get(1)?,
+        completed: row.get(2)?,
+        created_at: row.get(3)?,
+        created_at_date: row.get(4)?,
+        completed_at: row.get(5)?,
+        tags: Vec::new(),
+        project_id: row.get(6)?,
+        deleted_at: row.get(7)?,
+        position: row.get(8)?,
+        priority: Priority::from_level(row.get(9)?),
+        due_at: row.get(10)?,
+        // an unknown rule, e.g. from a newer version, leaves the task one-off
+        recurrence: row
+            .get::<_, Option<String>>(11)?
+            .and_then(|rule| rule.parse().ok()),
+        parent_id: row.get(12)?,
+        notes: row.get(13)?,
+    })
+}
+
+fn project_from_row(row: &Row) -> rusqlite::Result<Project> {
+    Ok(Project {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        color: row.get(2)?,
+        archived: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worklog;
+
+    #[test]
+    fn undoing_an_add_keeps_the_tracked_time() {
+        let store = Store::in_memory().unwrap();
+        let task = Task::new("write report".to_owned());
+        store.execute(&Command::add(task.clone())).unwrap();
+        let mut entry = TimeEntry::start(task.id.clone());
+        entry.started_at -= 2 * 60 * 60;
+        entry.stop();
+        store.add_time_entry(&entry).unwrap();
+
+        store.undo().unwrap();
+        assert!(store.get_all_tasks().unwrap().is_empty());
+
+        store.redo().unwrap();
+        let tasks = store.get_all_tasks().unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].deleted_at, None);
+        let entries = store.get_all_time_entries().unwrap();
+        assert_eq!(entries, vec![entry]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn opens_a_database_at_a_path_that_is_not_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = std::env::temp_dir().join(format!("small-worklog-{}", types::generate_uuid()));
+        let path = dir.join(OsStr::from_bytes(b"worklog-\xff.db"));
+        let store = Store::open(&path).unwrap();
+        assert!(!store.is_read_only());
+        assert!(!store.is_in_memory());
+        assert!(Store::in_memory().unwrap().is_in_memory());
+        drop(store);
+        assert!(path.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn quotes_search_input_for_fts() {
+        let cases = [
+            ("", None),
+            ("   ", None),
+            ("*", None),
+            ("-- () \"\"", None),
+            ("fix login", Some(r#""fix" "login""#)),
+            ("  fix   login  ", Some(r#""fix" "login""#)),
+            ("log*", Some(r#""log"*"#)),
+            ("log *", Some(r#""log""#)),
+            (r#""fix login""#, Some(r#""fix login""#)),
+            (r#""fix log"*"#, Some(r#""fix log"*"#)),
+            (r#""unclosed phrase"#, Some(r#""unclosed phrase""#)),
+            ("AND OR NOT", Some(r#""AND" "OR" "NOT""#)),
+            (r#"a"b"#, Some(r#""ab""#)),
+            ("col:value", Some(r#""col:value""#)),
+            ("NEAR(a b)", Some(r#""NEAR(a" "b)""#)),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(fts_query(input).as_deref(), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn search_input_is_never_a_syntax_error() {
+        let store = Store::in_memory().unwrap();
+        store
+            .add_task(&Task::new("fix login redirect".to_owned()))
+            .unwrap();
+        for input in [
+            "fix AND",
+            "\"login",
+            "redir*",
+            "NEAR(fix login)",
+            "^fix",
+            "a:b",
+            "-x",
+        ] {
+            store.search_tasks(input).unwrap();
+        }
+        assert_eq!(store.search_tasks("redir*").unwrap().len(), 1);
+        assert_eq!(store.search_tasks("\"fix login\"").unwrap().len(), 1);
+        assert_eq!(store.search_tasks("\"login fix\"").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn completing_or_trashing_a_task_stops_its_timer() {
+        let store = Store::in_memory().unwrap();
+        let mut tasks = Vec::new();
+        for description in ["timed", "subtask", "other"] {
+            let task = Task::new(description.to_owned());
+            store.execute(&Command::add(task.clone())).unwrap();
+            store
+                .add_time_entry(&TimeEntry::start(task.id.clone()))
+                .unwrap();
+            tasks.push(task);
+        }
+        let running = |store: &Store| -> Vec<String> {
+            let entries = store.backup().unwrap().time_entries;
+            let running = entries.into_iter().filter(|entry| entry.is_running());
+            running.map(|entry| entry.task_id).collect()
+        };
+
+        let mut completed = tasks[0].clone();
+        completed.set_completed(true);
+        store
+            .execute(&Command::update(tasks[0].clone(), completed))
+            .unwrap();
+        assert_eq!(running(&store), [tasks[1].id.clone(), tasks[2].id.clone()]);
+
+        store.execute(&Command::delete(tasks[1].clone())).unwrap();
+        assert_eq!(running(&store), [tasks[2].id.clone()]);
+
+        // undoing its add trashes a task too
+        store.undo().unwrap();
+        store.undo().unwrap();
+        store.undo().unwrap();
+        assert!(running(&store).is_empty());
+    }
+
+    #[test]
+    fn undoing_a_delete_brings_back_its_subtasks() {
+        let store = Store::in_memory().unwrap();
+        let parent = Task::new("release".to_owned());
+        store.execute(&Command::add(parent.clone())).unwrap();
+        for description in ["tag", "publish"] {
+            let mut subtask = Task::new(description.to_owned());
+            subtask.parent_id = Some(parent.id.clone());
+            store.execute(&Command::add(subtask)).unwrap();
+        }
+        let tasks = store.get_all_tasks().unwrap();
+        store
+            .execute_all(&worklog::delete_commands(&tasks, parent))
+            .unwrap();
+        assert!(store.get_all_tasks().unwrap().is_empty());
+        assert_eq!(store.get_trashed_tasks().unwrap().len(), 3);
+
+        // the task and its subtasks are one step
+        store.undo().unwrap();
+        assert_eq!(store.get_all_tasks().unwrap(), tasks);
+        assert!(store.get_trashed_tasks().unwrap().is_empty());
+
+        store.redo().unwrap();
+        assert!(store.get_all_tasks().unwrap().is_empty());
+        assert_eq!(store.get_trashed_tasks().unwrap().len(), 3);
+        assert!(store.redo().unwrap().is_none());
+    }
+
+    #[test]
+    fn a_new_change_forgets_what_was_undone() {
+        let store = Store::in_memory().unwrap();
+        let first = Task::new("first".to_owned());
+        store.execute(&Command::add(first.clone())).unwrap();
+        store.undo().unwrap();
+
+        let second = Task::new("second".to_owned());
+        store.execute(&Command::add(second.clone())).unwrap();
+        assert!(store.redo().unwrap().is_none());
+        assert_eq!(store.get_all_tasks().unwrap(), vec![second]);
+
+        // the undone change is gone from the history
+        store.undo().unwrap();
+        assert!(store.undo().unwrap().is_none());
+        assert!(store.get_all_tasks().unwrap().is_empty());
+    }
+}
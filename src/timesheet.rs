@@ -0,0 +1,408 @@
+//! Billable time over a date range, aggregated per day and project or tag.
+
+use crate::config::TimesheetConfig;
+use crate::export::{self, csv_field, DateRange};
+use crate::types::{Project, Task, TimeEntry};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+const NO_PROJECT: &str = "(no project)";
+const NO_TAG: &str = "(untagged)";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    Project,
+    /// A task's time counts towards its first tag only, so nothing is billed twice.
+    Tag,
+}
+
+impl GroupBy {
+    pub fn label(&self) -> &'static str {
+        match self {
+            GroupBy::Project => "project",
+            GroupBy::Tag => "tag",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TimesheetRow {
+    pub date: String,
+    pub group: String,
+    /// Tracked time after rounding.
+    pub seconds: u64,
+    pub rate: f64,
+    pub amount: f64,
+}
+
+impl TimesheetRow {
+    pub fn hours(&self) -> f64 {
+        self.seconds as f64 / 3600.0
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Timesheet {
+    pub from: String,
+    pub to: String,
+    pub group_by: GroupBy,
+    pub currency: String,
+    pub rows: Vec<TimesheetRow>,
+}
+
+impl Timesheet {
+    /// Add up the time tracked within `range`, one row per day and group,
+    /// each rounded up to the configured increment before the rate applies.
+    pub fn new(
+        tasks: &[Task],
+        projects: &[Project],
+        time_entries: &[TimeEntry],
+        range: DateRange,
+        group_by: GroupBy,
+        config: &TimesheetConfig,
+    ) -> Self {
+        let tasks: HashMap<&str, &Task> = tasks.iter().map(|t| (t.id.as_str(), t)).collect();
+        let entries_by_date = export::group_by_date(
+            time_entries
+                .iter()
+                .filter(|entry| range.contains(&entry.day().to_string())),
+            |entry| entry.day().to_string(),
+        );
+
+        let mut rows = Vec::new();
+        for (date, entries) in entries_by_date {
+            let mut seconds_by_group: BTreeMap<String, u64> = BTreeMap::new();
+            for entry in entries {
+                let Some(task) = tasks.get(entry.task_id.as_str()) else {
+                    continue;
+                };
+                let group = match group_by {
+                    GroupBy::Project => task
+                        .project_id
+                        .as_ref()
+                        .and_then(|id| projects.iter().find(|p| &p.id == id))
+                        .map_or(NO_PROJECT.to_owned(), |p| p.name.clone()),
+                    GroupBy::Tag => task.tags.first().cloned().unwrap_or(NO_TAG.to_owned()),
+                };
+                *seconds_by_group.entry(group).or_default() += entry.seconds();
+            }
+
+            for (group, seconds) in seconds_by_group {
+                let seconds = round_up(seconds, config.round_to_minutes);
+                let rate = rate_for(config, group_by, &group);
+                rows.push(TimesheetRow {
+                    date: date.clone(),
+                    group,
+                    seconds,
+                    rate,
+                    amount: seconds as f64 / 3600.0 * rate,
+                });
+            }
+        }
+
+        Timesheet {
+            from: range.from.to_string(),
+            to: range.to.to_string(),
+            group_by,
+            currency: config.currency.clone(),
+            rows,
+        }
+    }
+
+    pub fn total_hours(&self) -> f64 {
+        self.rows.iter().map(|row| row.hours()).sum()
+    }
+
+    pub fn total_amount(&self) -> f64 {
+        self.rows.iter().map(|row| row.amount).sum()
+    }
+
+    pub fn file_stem(&self) -> String {
+        format!("small-worklog-timesheet-{}-to-{}", self.from, self.to)
+    }
+
+    /// One row per day and group, followed by a total row.
+    pub fn to_csv(&self) -> String {
+        let mut out = format!("date,{},hours,rate,amount\r\n", self.group_by.label());
+        for row in self.rows.iter() {
+            let _ = write!(
+                out,
+                "{},{},{:.2},{:.2},{:.2}\r\n",
+                row.date,
+                csv_field(&row.group),
+                row.hours(),
+                row.rate,
+                row.amount
+            );
+        }
+        let _ = write!(
+            out,
+            "total,,{:.2},,{:.2}\r\n",
+            self.total_hours(),
+            self.total_amount()
+        );
+        out
+    }
+
+    /// A standalone page with one table, subtotals per day, meant for printing.
+    pub fn to_html(&self) -> String {
+        let title = format!("Timesheet {} – {}", self.from, self.to);
+        let mut out = String::new();
+        let _ = writeln!(out, "<!DOCTYPE html>");
+        let _ = writeln!(out, "<html><head><meta charset=\"utf-8\">");
+        let _ = writeln!(out, "<title>{}</title>", escape_html(&title));
+        let _ = writeln!(
+            out,
+            "<style>body {{ font-family: sans-serif; margin: 2em; }} \
+             table {{ border-collapse: collapse; width: 100%; }} \
+             th, td {{ border-bottom: 1px solid #ccc; padding: 4px 8px; text-align: left; }} \
+             td.num, th.num {{ text-align: right; }} \
+             tr.subtotal td, tr.total td {{ font-weight: bold; }} \
+             tr.total td {{ border-top: 2px solid #000; }}</style>"
+        );
+        let _ = writeln!(out, "</head><body>");
+        let _ = writeln!(out, "<h1>{}</h1>", escape_html(&title));
+        let _ = writeln!(out, "<table>");
+        let _ = writeln!(
+            out,
+            "<tr><th>Date</th><th>{}</th><th class=\"num\">Hours</th><th class=\"num\">Rate</th><th class=\"num\">Amount</th></tr>",
+            capitalize(self.group_by.label())
+        );
+
+        let rows_by_date = export::group_by_date(self.rows.iter(), |row| row.date.clone());
+        for (date, rows) in rows_by_date.iter() {
+            for (index, row) in rows.iter().enumerate() {
+                let date = if index == 0 { date.as_str() } else { "" };
+                let _ = writeln!(
+                    out,
+                    "<tr><td>{}</td><td>{}</td><td class=\"num\">{:.2}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>",
+                    date,
+                    escape_html(&row.group),
+                    row.hours(),
+                    self.money(row.rate),
+                    self.money(row.amount)
+                );
+            }
+            if rows.len() > 1 {
+                let hours: f64 = rows.iter().map(|row| row.hours()).sum();
+                let amount: f64 = rows.iter().map(|row| row.amount).sum();
+                let _ = writeln!(
+                    out,
+                    "<tr class=\"subtotal\"><td></td><td></td><td class=\"num\">{:.2}</td><td></td><td class=\"num\">{}</td></tr>",
+                    hours,
+                    self.money(amount)
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "<tr class=\"total\"><td>Total</td><td></td><td class=\"num\">{:.2}</td><td></td><td class=\"num\">{}</td></tr>",
+            self.total_hours(),
+            self.money(self.total_amount())
+        );
+        let _ = writeln!(out, "</table>");
+        if self.rows.is_empty() {
+            let _ = writeln!(out, "<p><em>No time tracked.</em></p>");
+        }
+        let _ = writeln!(out, "</body></html>");
+        out
+    }
+
+    fn money(&self, value: f64) -> String {
+        if self.currency.is_empty() {
+            format!("{:.2}", value)
+        } else {
+            format!("{:.2} {}", value, escape_html(&self.currency))
+        }
+    }
+}
+
+/// Round up to a multiple of `minutes`, or to a whole minute for 0, so that
+/// hours and amounts match what the app shows.
+fn round_up(seconds: u64, minutes: u32) -> u64 {
+    let increment = minutes.max(1) as u64 * 60;
+    (seconds + increment - 1) / increment * increment
+}
+
+fn rate_for(config: &TimesheetConfig, group_by: GroupBy, group: &str) -> f64 {
+    let rates = match group_by {
+        GroupBy::Project => &config.project_rates,
+        GroupBy::Tag => &config.tag_rates,
+    };
+    rates
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(group))
+        .map_or(config.default_rate, |(_, rate)| *rate)
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day;
+    use chrono::{NaiveDate, NaiveTime};
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, day).unwrap()
+    }
+
+    /// `minutes` tracked on `task` from 9am on the given day of October.
+    fn entry(task: &Task, day: u32, minutes: u64) -> TimeEntry {
+        let started_at = day::at(date(day), NaiveTime::from_hms_opt(9, 0, 0).unwrap());
+        TimeEntry {
+            id: crate::types::generate_uuid(),
+            task_id: task.id.clone(),
+            started_at,
+            stopped_at: Some(started_at + minutes * 60),
+        }
+    }
+
+    fn config() -> TimesheetConfig {
+        TimesheetConfig {
+            currency: "EUR".to_owned(),
+            default_rate: 80.0,
+            round_to_minutes: 15,
+            project_rates: BTreeMap::from([("Acme".to_owned(), 120.0)]),
+            tag_rates: BTreeMap::from([("support".to_owned(), 60.0)]),
+        }
+    }
+
+    #[test]
+    fn rounds_up_to_the_increment() {
+        let cases = [
+            (0, 15, 0),
+            (1, 15, 15 * 60),
+            (15 * 60, 15, 15 * 60),
+            (15 * 60 + 1, 15, 30 * 60),
+            (59 * 60, 60, 60 * 60),
+            (0, 0, 0),
+            (1, 0, 60),
+            (60, 0, 60),
+            (61, 0, 120),
+            (90 * 60 + 30, 0, 91 * 60),
+        ];
+        for (seconds, minutes, expected) in cases {
+            assert_eq!(
+                round_up(seconds, minutes),
+                expected,
+                "{seconds}s to {minutes}m"
+            );
+        }
+    }
+
+    #[test]
+    fn looks_up_rates_ignoring_case() {
+        let config = config();
+        let cases = [
+            (GroupBy::Project, "Acme", 120.0),
+            (GroupBy::Project, "acme", 120.0),
+            (GroupBy::Project, "ACME", 120.0),
+            (GroupBy::Project, "Initech", 80.0),
+            (GroupBy::Project, NO_PROJECT, 80.0),
+            // tag rates only apply when grouping by tag
+            (GroupBy::Project, "support", 80.0),
+            (GroupBy::Tag, "Support", 60.0),
+            (GroupBy::Tag, "acme", 80.0),
+            (GroupBy::Tag, NO_TAG, 80.0),
+        ];
+        for (group_by, group, rate) in cases {
+            assert_eq!(rate_for(&config, group_by, group), rate, "{group:?}");
+        }
+    }
+
+    #[test]
+    fn adds_up_time_per_day_and_group() {
+        let acme = Project::new("Acme".to_owned());
+        let mut billed = Task::new("fix login".to_owned());
+        billed.project_id = Some(acme.id.clone());
+        billed.tags = vec!["support".to_owned(), "bug".to_owned()];
+        let internal = Task::new("plan sprint".to_owned());
+        let unknown = Task::new("purged".to_owned());
+        let tasks = [billed.clone(), internal.clone()];
+        let entries = [
+            entry(&billed, 12, 20),
+            entry(&billed, 12, 10),
+            entry(&internal, 12, 1),
+            entry(&billed, 13, 45),
+            // outside the range, or of a task that is gone
+            entry(&billed, 11, 60),
+            entry(&billed, 15, 60),
+            entry(&unknown, 12, 60),
+        ];
+        let range = DateRange {
+            from: date(12),
+            to: date(14),
+        };
+
+        let timesheet = Timesheet::new(
+            &tasks,
+            std::slice::from_ref(&acme),
+            &entries,
+            range,
+            GroupBy::Project,
+            &config(),
+        );
+        let rows: Vec<(&str, &str, u64, f64)> = timesheet
+            .rows
+            .iter()
+            .map(|row| {
+                (
+                    row.date.as_str(),
+                    row.group.as_str(),
+                    row.seconds / 60,
+                    row.amount,
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("2026-10-12", "(no project)", 15, 20.0),
+                ("2026-10-12", "Acme", 30, 60.0),
+                ("2026-10-13", "Acme", 45, 90.0),
+            ]
+        );
+        assert_eq!(timesheet.total_hours(), 1.5);
+        assert_eq!(timesheet.total_amount(), 170.0);
+        assert_eq!(
+            timesheet.to_csv(),
+            "date,project,hours,rate,amount\r\n\
+             2026-10-12,(no project),0.25,80.00,20.00\r\n\
+             2026-10-12,Acme,0.50,120.00,60.00\r\n\
+             2026-10-13,Acme,0.75,120.00,90.00\r\n\
+             total,,1.50,,170.00\r\n"
+        );
+
+        let by_tag = Timesheet::new(&tasks, &[acme], &entries, range, GroupBy::Tag, &config());
+        let rows: Vec<(&str, &str, f64)> = by_tag
+            .rows
+            .iter()
+            .map(|row| (row.date.as_str(), row.group.as_str(), row.rate))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("2026-10-12", "(untagged)", 80.0),
+                ("2026-10-12", "support", 60.0),
+                ("2026-10-13", "support", 60.0),
+            ]
+        );
+    }
+}
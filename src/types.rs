@@ -1,9 +1,12 @@
-use chrono::{DateTime, Utc};
+use crate::day;
+use crate::recurrence::Recurrence;
+use chrono::NaiveDate;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Task {
     pub id: String,
     pub description: String,
@@ -11,6 +14,276 @@ pub struct Task {
     pub created_at: u64, // Use a u64 to represent the timestamp
     pub created_at_date: String,
     pub completed_at: u64, // Use a u64 to represent the timestamp
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub project_id: Option<String>,
+    /// Where the task goes in the list, lowest first.
+    #[serde(default)]
+    pub position: i64,
+    #[serde(default)]
+    pub priority: Priority,
+    /// When the task is due, see `day::start_of` for dates without a time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<Recurrence>,
+    /// The task this one is a step of. Subtasks are one level deep.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    /// Longer context in Markdown, like links and findings.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    /// When the task was moved to the trash, `None` for live tasks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Project {
+    pub id: String,
+    pub name: String,
+    pub color: String, // "#rrggbb"
+    pub archived: bool,
+    pub created_at: u64,
+}
+
+/// How urgent a task is, typed as `!1` (urgent) to `!4` (low) in quick-add.
+/// Ordered from `None` up to `Urgent`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    #[default]
+    None,
+    Low,
+    Medium,
+    High,
+    Urgent,
+}
+
+impl Priority {
+    /// Most urgent first, as offered in menus.
+    pub const ALL: [Priority; 5] = [
+        Priority::Urgent,
+        Priority::High,
+        Priority::Medium,
+        Priority::Low,
+        Priority::None,
+    ];
+
+    /// The value stored in the database, 0 for none up to 4 for urgent.
+    pub fn level(self) -> i64 {
+        self as i64
+    }
+
+    pub fn from_level(level: i64) -> Self {
+        match level {
+            1 => Priority::Low,
+            2 => Priority::Medium,
+            3 => Priority::High,
+            4 => Priority::Urgent,
+            _ => Priority::None,
+        }
+    }
+
+    /// The priority of a `!1`–`!4` token, `!1` being the most urgent.
+    pub fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "!1" => Some(Priority::Urgent),
+            "!2" => Some(Priority::High),
+            "!3" => Some(Priority::Medium),
+            "!4" => Some(Priority::Low),
+            _ => None,
+        }
+    }
+
+    pub fn token(self) -> Option<&'static str> {
+        match self {
+            Priority::None => None,
+            Priority::Low => Some("!4"),
+            Priority::Medium => Some("!3"),
+            Priority::High => Some("!2"),
+            Priority::Urgent => Some("!1"),
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Priority::None => "None",
+            Priority::Low => "Low",
+            Priority::Medium => "Medium",
+            Priority::High => "High",
+            Priority::Urgent => "Urgent",
+        }
+    }
+}
+
+/// Where an open task stands against its due date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Due {
+    Overdue,
+    Today,
+    /// Due this many days from today.
+    Upcoming(i64),
+}
+
+/// One interval of work on a task. `stopped_at` is `None` while the timer runs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TimeEntry {
+    pub id: String,
+    pub task_id: String,
+    pub started_at: u64,
+    pub stopped_at: Option<u64>,
+}
+
+impl TimeEntry {
+    pub fn start(task_id: String) -> Self {
+        TimeEntry {
+            id: generate_uuid(),
+            task_id,
+            started_at: now(),
+            stopped_at: None,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.stopped_at.is_none()
+    }
+
+    pub fn stop(&mut self) {
+        self.stopped_at = Some(now().max(self.started_at));
+    }
+
+    /// Seconds tracked so far, counting a running timer up to now.
+    pub fn seconds(&self) -> u64 {
+        self.stopped_at
+            .unwrap_or_else(now)
+            .saturating_sub(self.started_at)
+    }
+
+    /// The local day the interval started on.
+    pub fn day(&self) -> NaiveDate {
+        day::day_of(self.started_at)
+    }
+}
+
+/// Marks the start and end of a matched term in `SearchHit::highlighted`.
+pub const HIGHLIGHT_START: char = '\u{2}';
+pub const HIGHLIGHT_END: char = '\u{3}';
+
+/// A task matching a full-text search, best match first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub task_id: String,
+    /// The description with matches wrapped in `HIGHLIGHT_START`/`HIGHLIGHT_END`.
+    pub highlighted: String,
+}
+
+/// How many of each task's subtasks are done, and how many it has, by parent
+/// id. Tasks without subtasks are left out.
+pub fn subtask_progress(tasks: &[Task]) -> HashMap<&str, (usize, usize)> {
+    let mut progress: HashMap<&str, (usize, usize)> = HashMap::new();
+    for task in tasks {
+        if let Some(parent_id) = &task.parent_id {
+            let (done, total) = progress.entry(parent_id).or_default();
+            *done += usize::from(task.completed);
+            *total += 1;
+        }
+    }
+    progress
+}
+
+/// Put each subtask right after its parent, keeping the order otherwise.
+/// Subtasks whose parent is not among `tasks` stay where they are. Returns
+/// each task with whether it is nested under the one before.
+pub fn nest<'a>(tasks: &[&'a Task]) -> Vec<(&'a Task, bool)> {
+    let listed = |id: &String| tasks.iter().any(|task| &task.id == id);
+    let mut nested = Vec::with_capacity(tasks.len());
+    for task in tasks
+        .iter()
+        .filter(|task| !task.parent_id.as_ref().is_some_and(listed))
+    {
+        nested.push((*task, false));
+        let subtasks = tasks
+            .iter()
+            .filter(|subtask| subtask.parent_id.as_ref() == Some(&task.id));
+        nested.extend(subtasks.map(|subtask| (*subtask, true)));
+    }
+    nested
+}
+
+/// Total seconds tracked on each task, by task id.
+pub fn tracked_seconds(entries: &[TimeEntry]) -> HashMap<&str, u64> {
+    let mut tracked: HashMap<&str, u64> = HashMap::new();
+    for entry in entries {
+        *tracked.entry(&entry.task_id).or_default() += entry.seconds();
+    }
+    tracked
+}
+
+/// Total seconds tracked on each day, by the day the intervals started on.
+pub fn tracked_by_day(entries: &[TimeEntry]) -> BTreeMap<NaiveDate, u64> {
+    let mut tracked: BTreeMap<NaiveDate, u64> = BTreeMap::new();
+    for entry in entries {
+        *tracked.entry(entry.day()).or_default() += entry.seconds();
+    }
+    tracked
+}
+
+/// A duration as `1:05` (hours and minutes), or `0:00:42` when `with_seconds`.
+pub fn format_duration(seconds: u64, with_seconds: bool) -> String {
+    let (hours, minutes) = (seconds / 3600, seconds / 60 % 60);
+    if with_seconds {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds % 60)
+    } else {
+        format!("{}:{:02}", hours, minutes)
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+impl Project {
+    pub fn new(name: String) -> Self {
+        let now = SystemTime::now();
+        let created_at = now
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        let mut rng = rand::thread_rng();
+        let color = format!(
+            "#{:02x}{:02x}{:02x}",
+            rng.gen_range(64..=224u8),
+            rng.gen_range(64..=224u8),
+            rng.gen_range(64..=224u8)
+        );
+
+        Project {
+            id: generate_uuid(),
+            name,
+            color,
+            archived: false,
+            created_at,
+        }
+    }
+
+    pub fn rgb(&self) -> [u8; 3] {
+        let hex = self.color.trim_start_matches('#');
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                .unwrap_or(128)
+        };
+        [channel(0), channel(2), channel(4)]
+    }
+
+    pub fn set_rgb(&mut self, rgb: [u8; 3]) {
+        self.color = format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2]);
+    }
 }
 
 impl Task {
@@ -20,7 +293,7 @@ impl Task {
             .duration_since(UNIX_EPOCH)
             .expect("Time went backwards")
             .as_secs();
-        let created_at_date = chrono::Local::now().format("%Y-%m-%d").to_string();
+        let created_at_date = day::day_of(created_at).to_string();
 
         Task {
             id: generate_uuid(),
@@ -29,21 +302,82 @@ impl Task {
             created_at,
             created_at_date,
             completed_at: 0,
+            tags: Vec::new(),
+            project_id: None,
+            position: 0,
+            priority: Priority::None,
+            due_at: None,
+            recurrence: None,
+            parent_id: None,
+            notes: String::new(),
+            deleted_at: None,
         }
     }
 
+    /// Mark the task as (un)completed, keeping `completed_at` in sync.
+    pub fn set_completed(&mut self, completed: bool) {
+        self.completed = completed;
+        self.completed_at = if completed {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_secs()
+        } else {
+            0
+        };
+    }
+
+    pub fn due_day(&self) -> Option<NaiveDate> {
+        self.due_at.map(day::day_of)
+    }
+
+    /// `None` for completed tasks and tasks without a due date. A task due
+    /// at a time is overdue once that time has passed, otherwise at the end
+    /// of its day.
+    pub fn due(&self) -> Option<Due> {
+        if self.completed {
+            return None;
+        }
+        let due_at = self.due_at?;
+        let (due_day, today) = (day::day_of(due_at), day::today());
+        if due_day < today || (day::has_time(due_at) && due_at < now()) {
+            Some(Due::Overdue)
+        } else if due_day == today {
+            Some(Due::Today)
+        } else {
+            Some(Due::Upcoming((due_day - today).num_days()))
+        }
+    }
+
+    /// Whether the task is overdue or due within `days` days.
+    pub fn is_due_within(&self, days: i64) -> bool {
+        match self.due() {
+            Some(Due::Overdue | Due::Today) => true,
+            Some(Due::Upcoming(in_days)) => in_days <= days,
+            None => false,
+        }
+    }
+
+    pub fn is_trashed(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    /// The local day the task was created on, see `day` for how days are cut.
+    pub fn day(&self) -> NaiveDate {
+        day::day_of(self.created_at)
+    }
+
     pub fn is_today(&self) -> bool {
-        let today = Utc::now().date_naive();
-        let task_date = DateTime::from_timestamp(self.created_at as i64, 0)
-            .unwrap()
-            .date_naive();
-        task_date == today
+        self.day() == day::today()
     }
 
     // Function to get the date string from created_at timestamp
     pub fn created_at_date(&self) -> String {
-        let created_at_datetime = DateTime::from_timestamp(self.created_at as i64, 0).unwrap();
-        created_at_datetime.date_naive().to_string()
+        self.day().to_string()
     }
 }
 
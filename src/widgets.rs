@@ -0,0 +1,371 @@
+use crate::day;
+use crate::markdown;
+use crate::recurrence::{self, Recurrence};
+use crate::types::{Due, Priority, Project, Task, HIGHLIGHT_END, HIGHLIGHT_START};
+use eframe::egui::{self, Color32, RichText, Ui};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// A stable color for a tag, so the same tag looks the same everywhere.
+pub fn tag_color(tag: &str) -> Color32 {
+    let mut hasher = DefaultHasher::new();
+    tag.hash(&mut hasher);
+    let hue = (hasher.finish() % 360) as f32 / 360.0;
+    egui::ecolor::Hsva::new(hue, 0.45, 0.75, 1.0).into()
+}
+
+/// Draw a small colored `#tag` chip. Returns the response so callers can
+/// react to clicks, e.g. to filter by the tag.
+pub fn tag_chip(ui: &mut Ui, tag: &str, selected: bool) -> egui::Response {
+    let color = tag_color(tag);
+    let text = RichText::new(format!("#{}", tag))
+        .small()
+        .color(if selected { Color32::BLACK } else { color });
+    let fill = if selected {
+        color
+    } else {
+        color.gamma_multiply(0.15)
+    };
+    ui.add(
+        egui::Button::new(text)
+            .fill(fill)
+            .rounding(8.0)
+            .stroke(egui::Stroke::new(1.0, color)),
+    )
+    .on_hover_text(format!("Filter by #{}", tag))
+}
+
+/// Show the active tag filter with a button to clear it.
+pub fn tag_filter_bar(ui: &mut Ui, tag_filter: &mut Option<String>) {
+    let Some(tag) = tag_filter.clone() else {
+        return;
+    };
+    ui.horizontal(|ui| {
+        ui.label(RichText::new("filter:").small().weak());
+        if tag_chip(ui, &tag, true).clicked() || ui.small_button("✖").clicked() {
+            *tag_filter = None;
+        }
+    });
+}
+
+/// Clicking a chip toggles it as the current filter.
+pub fn toggle_tag_filter(tag_filter: &mut Option<String>, tag: &str) {
+    if tag_filter.as_deref() == Some(tag) {
+        *tag_filter = None;
+    } else {
+        *tag_filter = Some(tag.to_owned());
+    }
+}
+
+pub fn project_color(project: &Project) -> Color32 {
+    let [r, g, b] = project.rgb();
+    Color32::from_rgb(r, g, b)
+}
+
+/// A colored dot followed by the project name.
+pub fn project_label(ui: &mut Ui, project: &Project) -> egui::Response {
+    ui.label(
+        RichText::new(format!("● {}", project.name))
+            .small()
+            .color(project_color(project)),
+    )
+}
+
+/// Red for urgent down to blue for low, `None` without a priority.
+pub fn priority_color(priority: Priority) -> Option<Color32> {
+    match priority {
+        Priority::None => None,
+        Priority::Low => Some(Color32::from_rgb(90, 150, 220)),
+        Priority::Medium => Some(Color32::from_rgb(220, 180, 50)),
+        Priority::High => Some(Color32::from_rgb(235, 125, 40)),
+        Priority::Urgent => Some(Color32::from_rgb(225, 55, 55)),
+    }
+}
+
+/// The task's `!1`–`!4` token in its priority's color, right-click to change
+/// it. Tasks without a priority get an invisible one to keep rows aligned.
+/// Returns whether the priority was changed.
+pub fn priority_marker(ui: &mut Ui, priority: &mut Priority) -> bool {
+    let text = RichText::new(priority.token().unwrap_or("!4"))
+        .small()
+        .strong()
+        .color(priority_color(*priority).unwrap_or(Color32::TRANSPARENT));
+    let hover = match priority {
+        Priority::None => "No priority, right-click to set one".to_owned(),
+        _ => format!("{} priority, right-click to change", priority.label()),
+    };
+    let mut changed = false;
+    ui.add(egui::Label::new(text).sense(egui::Sense::click()))
+        .on_hover_text(hover)
+        .context_menu(|ui| {
+            for choice in Priority::ALL {
+                let label = match choice.token() {
+                    Some(token) => format!("{} {}", token, choice.label()),
+                    None => choice.label().to_owned(),
+                };
+                if ui.radio_value(priority, choice, label).clicked() {
+                    changed = true;
+                    ui.close_menu();
+                }
+            }
+        });
+    changed
+}
+
+/// A badge for an open task's due date, red when overdue and orange when
+/// due today. Returns `None` for tasks that are not due.
+pub fn due_badge(ui: &mut Ui, task: &Task) -> Option<egui::Response> {
+    let (due, due_at, due_day) = (task.due()?, task.due_at?, task.due_day()?);
+    let time = if day::has_time(due_at) {
+        format!(" {}", day::time_of(due_at))
+    } else {
+        String::new()
+    };
+    let (text, color) = match due {
+        Due::Overdue => ("overdue".to_owned(), Some(Color32::from_rgb(225, 55, 55))),
+        Due::Today => (
+            format!("due today{}", time),
+            Some(Color32::from_rgb(235, 125, 40)),
+        ),
+        Due::Upcoming(1) => (format!("due tomorrow{}", time), None),
+        Due::Upcoming(days) if days < 7 => (format!("due {}{}", due_day.format("%a"), time), None),
+        Due::Upcoming(_) => (format!("due {}{}", due_day.format("%b %-d"), time), None),
+    };
+    let text = RichText::new(text).small();
+    let text = match color {
+        Some(color) => text.color(Color32::BLACK).background_color(color),
+        None => text.weak(),
+    };
+    let response = ui
+        .add(egui::Label::new(text).sense(egui::Sense::click()))
+        .on_hover_text(format!("Due {}{}, click to change", due_day, time));
+    Some(response)
+}
+
+/// What the due date typed into the quick-add box was read as, before the
+/// task is added.
+pub fn due_preview(ui: &mut Ui, phrase: &str, due_at: u64) -> egui::Response {
+    let mut text = format!("📅 {}", day::day_of(due_at).format("%a, %b %-d"));
+    if day::has_time(due_at) {
+        text.push_str(&format!(" {}", day::time_of(due_at)));
+    }
+    ui.label(
+        RichText::new(text)
+            .small()
+            .background_color(ui.visuals().selection.bg_fill),
+    )
+    .on_hover_text(format!("Due date read from \"{}\"", phrase))
+}
+
+/// Pick how a task repeats, `None` for never. Returns whether the rule was
+/// changed.
+pub fn recurrence_editor(ui: &mut Ui, recurrence: &mut Option<Recurrence>) -> bool {
+    use chrono::{Datelike, Weekday};
+
+    let today = day::today();
+    let choices = [
+        ("Never", None),
+        ("Every day", Some(Recurrence::Daily)),
+        ("Every weekday", Some(Recurrence::Weekdays)),
+        ("Weekly", Some(Recurrence::Weekly(vec![today.weekday()]))),
+        ("Monthly", Some(Recurrence::Monthly(today.day()))),
+        ("After completion", Some(Recurrence::AfterCompletion(7))),
+    ];
+    let kind = |rule: &Option<Recurrence>| rule.as_ref().map(std::mem::discriminant);
+    let current = choices
+        .iter()
+        .find(|(_, rule)| kind(rule) == kind(recurrence))
+        .map_or("Never", |(label, _)| *label);
+
+    let mut changed = false;
+    ui.vertical(|ui| {
+        egui::ComboBox::from_id_source("recurrence_kind")
+            .selected_text(current)
+            .show_ui(ui, |ui| {
+                for (label, rule) in choices.iter() {
+                    let selected = kind(rule) == kind(recurrence);
+                    if ui.selectable_label(selected, *label).clicked() && !selected {
+                        *recurrence = rule.clone();
+                        changed = true;
+                    }
+                }
+            });
+
+        match recurrence {
+            Some(Recurrence::Weekly(weekdays)) => {
+                ui.horizontal(|ui| {
+                    let week = std::iter::successors(Some(Weekday::Mon), |w| Some(w.succ()));
+                    for weekday in week.take(7) {
+                        let on = weekdays.contains(&weekday);
+                        if ui.selectable_label(on, weekday.to_string()).clicked() {
+                            if on {
+                                weekdays.retain(|w| *w != weekday);
+                            } else {
+                                weekdays.push(weekday);
+                                weekdays.sort_by_key(|w| w.num_days_from_monday());
+                            }
+                            changed = true;
+                        }
+                    }
+                });
+            }
+            Some(Recurrence::Monthly(day)) => {
+                let drag = egui::DragValue::new(day)
+                    .clamp_range(1..=31)
+                    .prefix("on day ");
+                changed |= ui.add(drag).changed();
+            }
+            Some(Recurrence::AfterCompletion(days)) => {
+                let drag = egui::DragValue::new(days)
+                    .clamp_range(1..=recurrence::MAX_DAYS_AFTER_COMPLETION)
+                    .suffix(" days after completion");
+                changed |= ui.add(drag).changed();
+            }
+            _ => {}
+        }
+    });
+    changed
+}
+
+/// How many subtasks are done, like `3/5`, green once all are.
+pub fn subtask_progress(ui: &mut Ui, done: usize, total: usize) -> egui::Response {
+    let text = RichText::new(format!("{}/{}", done, total)).small();
+    let text = if done == total {
+        text.color(Color32::DARK_GREEN)
+    } else {
+        text.weak()
+    };
+    ui.label(text)
+        .on_hover_text(format!("{} of {} subtasks done", done, total))
+}
+
+/// Which tasks a list shows and whether it puts the urgent ones first.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PriorityView {
+    pub sort: bool,
+    /// Hide tasks below this priority, `None` shows everything.
+    pub at_least: Priority,
+}
+
+impl PriorityView {
+    pub fn is_active(&self) -> bool {
+        self.sort || self.at_least != Priority::None
+    }
+
+    pub fn matches(&self, task: &Task) -> bool {
+        task.priority >= self.at_least
+    }
+
+    /// Most urgent first, keeping the list order among equal priorities.
+    pub fn sort(&self, tasks: &mut [&Task]) {
+        if self.sort {
+            tasks.sort_by_key(|task| std::cmp::Reverse(task.priority));
+        }
+    }
+}
+
+/// A small menu to sort and filter a task list by priority.
+pub fn priority_menu(ui: &mut Ui, view: &mut PriorityView) {
+    let title = if view.is_active() {
+        RichText::new("Priority ⏷").strong()
+    } else {
+        RichText::new("Priority ⏷").weak()
+    };
+    ui.menu_button(title, |ui| {
+        ui.checkbox(&mut view.sort, "Most urgent first");
+        ui.separator();
+        for choice in Priority::ALL.into_iter().rev() {
+            let label = match choice {
+                Priority::None => "All tasks".to_owned(),
+                Priority::Urgent => "Urgent only".to_owned(),
+                _ => format!("{} and above", choice.label()),
+            };
+            if ui.radio_value(&mut view.at_least, choice, label).clicked() {
+                ui.close_menu();
+            }
+        }
+    });
+}
+
+pub enum EditAction {
+    Continue,
+    Commit,
+    Cancel,
+}
+
+/// A single line editor used to fix a task description in place. Enter or
+/// clicking elsewhere commits, Escape cancels.
+pub fn inline_editor(ui: &mut Ui, text: &mut String) -> EditAction {
+    let response = ui.add(egui::TextEdit::singleline(text).desired_width(f32::INFINITY));
+    if response.lost_focus() {
+        if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+            EditAction::Cancel
+        } else {
+            EditAction::Commit
+        }
+    } else {
+        if !response.has_focus() {
+            response.request_focus();
+        }
+        EditAction::Continue
+    }
+}
+
+/// A task description that can be double-clicked to start editing it.
+pub fn editable_label(ui: &mut Ui, text: impl Into<egui::WidgetText>) -> egui::Response {
+    ui.add(egui::Label::new(text).sense(egui::Sense::click()))
+        .on_hover_text("Double-click to edit")
+}
+
+/// A task description that can be double-clicked to start editing it, with
+/// its inline Markdown rendered unless `raw`. `background` highlights it.
+pub fn description_label(
+    ui: &mut Ui,
+    text: &str,
+    background: Option<Color32>,
+    raw: bool,
+) -> egui::Response {
+    if !raw {
+        return markdown::description(ui, text, background).on_hover_text("Double-click to edit");
+    }
+    let mut text = egui::RichText::new(text);
+    if let Some(background) = background {
+        text = text.background_color(background);
+    }
+    editable_label(ui, text)
+}
+
+/// Play/pause toggle for a task's timer.
+pub fn timer_button(ui: &mut Ui, running: bool) -> egui::Response {
+    let (icon, hover) = if running {
+        ("⏸", "Stop the timer")
+    } else {
+        ("▶", "Start a timer, stopping any other")
+    };
+    ui.add(egui::Button::new(icon).small().frame(false))
+        .on_hover_text(hover)
+}
+
+/// A label for text with search matches wrapped in `HIGHLIGHT_START` and
+/// `HIGHLIGHT_END`, drawn with the selection color behind them.
+pub fn highlighted_label(ui: &mut Ui, text: &str) -> egui::Response {
+    let font_id = egui::TextStyle::Body.resolve(ui.style());
+    let normal = egui::TextFormat::simple(font_id.clone(), ui.visuals().text_color());
+    let highlighted = egui::TextFormat {
+        background: ui.visuals().selection.bg_fill,
+        color: ui.visuals().strong_text_color(),
+        ..egui::TextFormat::simple(font_id, ui.visuals().text_color())
+    };
+
+    let mut job = egui::text::LayoutJob::default();
+    let mut rest = text;
+    while let Some(start) = rest.find(HIGHLIGHT_START) {
+        job.append(&rest[..start], 0.0, normal.clone());
+        rest = &rest[start + HIGHLIGHT_START.len_utf8()..];
+        let end = rest.find(HIGHLIGHT_END).unwrap_or(rest.len());
+        job.append(&rest[..end], 0.0, highlighted.clone());
+        rest = rest.get(end + HIGHLIGHT_END.len_utf8()..).unwrap_or("");
+    }
+    job.append(rest, 0.0, normal);
+    ui.label(job)
+}
@@ -0,0 +1,488 @@
+use crate::command::Command;
+use crate::day;
+use crate::error::{Result, StoreError};
+use crate::export::Backup;
+use crate::recurrence;
+use crate::store::{self, Store};
+use crate::types::{Project, SearchHit, Task, TimeEntry};
+use chrono::NaiveDate;
+use std::sync::{Arc, Mutex};
+
+/// The one worklog every window and viewport talks to. Lock it only for the
+/// duration of a single read or write, never across drawing a whole panel.
+pub type SharedWorklog = Arc<Mutex<Worklog>>;
+
+/// The tasks and projects of the database, kept in memory so that drawing a
+/// frame never has to query SQLite. All writes go through here to keep the
+/// cache and the database in step; writes made by other connections (the CLI,
+/// another instance) are picked up by `refresh_if_changed`.
+pub struct Worklog {
+    store: Store,
+    tasks: Arc<Vec<Task>>,
+    trash: Arc<Vec<Task>>,
+    projects: Arc<Vec<Project>>,
+    time_entries: Arc<Vec<TimeEntry>>,
+    data_version: i64,
+    revision: u64,
+    errors: Vec<String>,
+    last_command: Option<Command>,
+    /// The day missed recurring tasks were last caught up with.
+    day: Option<NaiveDate>,
+    render_markdown: bool,
+}
+
+impl Worklog {
+    pub fn new(store: Store) -> Result<Self> {
+        let mut worklog = Worklog {
+            store,
+            tasks: Arc::default(),
+            trash: Arc::default(),
+            projects: Arc::default(),
+            time_entries: Arc::default(),
+            data_version: 0,
+            revision: 0,
+            errors: Vec::new(),
+            last_command: None,
+            day: None,
+            render_markdown: true,
+        };
+        worklog.reload()?;
+        // not being able to write, e.g. to a read-only file, is no reason to
+        // refuse reading
+        let result = worklog.catch_up_recurring();
+        worklog.report(result);
+        Ok(worklog)
+    }
+
+    pub fn shared(store: Store) -> Result<SharedWorklog> {
+        Ok(Arc::new(Mutex::new(Self::new(store)?)))
+    }
+
+    /// Switch to another database, e.g. when the profile changes.
+    pub fn reopen(&mut self, store: Store) -> Result<()> {
+        self.store = store;
+        self.reload()
+    }
+
+    /// Whether writes are refused, e.g. because the database file is read-only.
+    pub fn is_read_only(&self) -> bool {
+        self.store.is_read_only()
+    }
+
+    /// Whether nothing is saved because no database file could be opened.
+    pub fn is_in_memory(&self) -> bool {
+        self.store.is_in_memory()
+    }
+
+    /// Keep the error of a failed write for the error banner instead of
+    /// crashing, and hand back the value otherwise.
+    pub fn report<T>(&mut self, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.report_error(err);
+                None
+            }
+        }
+    }
+
+    pub fn report_error(&mut self, err: StoreError) {
+        eprintln!("worklog: {}", err);
+        // The read-only notice is shown permanently, no need to repeat it.
+        if !matches!(err, StoreError::ReadOnly) {
+            self.errors.push(err.to_string());
+        }
+        self.revision += 1;
+    }
+
+    /// Errors reported since the last call, oldest first.
+    pub fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Bumped on every change, so views can tell when to refresh.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// A cheap snapshot of all tasks, safe to hand to another viewport.
+    pub fn tasks(&self) -> Arc<Vec<Task>> {
+        self.tasks.clone()
+    }
+
+    /// Trashed tasks, most recently deleted first.
+    pub fn trash(&self) -> Arc<Vec<Task>> {
+        self.trash.clone()
+    }
+
+    pub fn projects(&self) -> Arc<Vec<Project>> {
+        self.projects.clone()
+    }
+
+    pub fn time_entries(&self) -> Arc<Vec<TimeEntry>> {
+        self.time_entries.clone()
+    }
+
+    /// Everything in the database, the trash included, read fresh from it.
+    pub fn backup(&self) -> Result<Backup> {
+        self.store.backup()
+    }
+
+    /// The timer currently running, at most one at a time.
+    pub fn running_timer(&self) -> Option<&TimeEntry> {
+        self.time_entries.iter().rev().find(|e| e.is_running())
+    }
+
+    /// Start tracking time on a task, stopping whatever timer was running.
+    pub fn start_timer(&mut self, task_id: &str) -> Result<()> {
+        self.stop_timer()?;
+        let entry = TimeEntry::start(task_id.to_owned());
+        self.store.add_time_entry(&entry)?;
+        Arc::make_mut(&mut self.time_entries).push(entry);
+        self.revision += 1;
+        Ok(())
+    }
+
+    pub fn stop_timer(&mut self) -> Result<()> {
+        let Some(mut entry) = self.running_timer().cloned() else {
+            return Ok(());
+        };
+        entry.stop();
+        self.store.update_time_entry(&entry)?;
+        if let Some(cached) = Arc::make_mut(&mut self.time_entries)
+            .iter_mut()
+            .find(|e| e.id == entry.id)
+        {
+            *cached = entry;
+        }
+        self.revision += 1;
+        Ok(())
+    }
+
+    pub fn search(&self, query: &str) -> Result<Vec<SearchHit>> {
+        self.store.search_tasks(query)
+    }
+
+    /// Reload the cache if another connection has written to the database
+    /// since it was loaded. Returns whether anything was reloaded.
+    pub fn refresh_if_changed(&mut self) -> Result<bool> {
+        if self.day != Some(day::today()) {
+            self.catch_up_recurring()?;
+        }
+        if self.store.data_version()? == self.data_version {
+            return Ok(false);
+        }
+        self.reload()?;
+        Ok(true)
+    }
+
+    pub fn reload(&mut self) -> Result<()> {
+        self.data_version = self.store.data_version()?;
+        self.tasks = Arc::new(self.store.get_all_tasks()?);
+        self.trash = Arc::new(self.store.get_trashed_tasks()?);
+        self.projects = Arc::new(self.store.get_all_projects()?);
+        self.time_entries = Arc::new(self.store.get_all_time_entries()?);
+        self.render_markdown = self.store.render_markdown()?;
+        self.revision += 1;
+        Ok(())
+    }
+
+    /// The command the last task change ran as, if not taken yet, e.g. to
+    /// offer undoing it.
+    pub fn take_last_command(&mut self) -> Option<Command> {
+        self.last_command.take()
+    }
+
+    fn execute(&mut self, command: Command) -> Result<()> {
+        self.store.execute(&command)?;
+        self.last_command = Some(command);
+        Ok(())
+    }
+
+    /// Run commands as one undo step, the first one standing for all of them.
+    fn execute_all(&mut self, commands: Vec<Command>) -> Result<()> {
+        self.store.execute_all(&commands)?;
+        self.last_command = commands.into_iter().next();
+        Ok(())
+    }
+
+    /// Give each recurring task missed before today its next occurrence.
+    /// Runs once a day.
+    fn catch_up_recurring(&mut self) -> Result<()> {
+        let today = day::today();
+        self.day = Some(today);
+        let position = self.store.next_position()?;
+        let batches = recurrence::catch_up(&self.tasks, today, position);
+        if batches.is_empty() {
+            return Ok(());
+        }
+        for commands in batches {
+            self.store.execute_all(&commands)?;
+        }
+        self.reload()
+    }
+
+    /// Add a task at the end of the list.
+    pub fn add_task(&mut self, mut task: Task) -> Result<()> {
+        task.position = self.store.next_position()?;
+        self.execute(Command::add(task.clone()))?;
+        Arc::make_mut(&mut self.tasks).push(task);
+        self.revision += 1;
+        Ok(())
+    }
+
+    /// Completing a task stops its timer, completing a recurring task adds
+    /// its next occurrence, and completing the last open subtask can complete
+    /// the parent.
+    pub fn update_task(&mut self, task: &Task) -> Result<()> {
+        let before = match self.tasks.iter().find(|t| t.id == task.id) {
+            Some(cached) => cached.clone(),
+            None => self
+                .store
+                .get_task(&task.id)?
+                .unwrap_or_else(|| task.clone()),
+        };
+        let position = self.store.next_position()?;
+        let parent = match self.auto_complete_parent() {
+            true => parent_update(&self.tasks, &before, task),
+            false => None,
+        };
+        let mut commands = recurrence::update(before, task.clone(), position);
+        if let Some((before, after)) = parent {
+            commands.extend(recurrence::update(before, after, position + 1));
+        }
+
+        // the store stops the timer of a completed task
+        let stops_timer = self.running_timer().is_some_and(|entry| {
+            commands.iter().any(|command| {
+                let after = command.after.as_ref();
+                after.is_some_and(|t| t.completed && t.id == entry.task_id)
+            })
+        });
+        let changes_others = commands.len() > 1;
+        self.execute_all(commands)?;
+        if changes_others || stops_timer {
+            return self.reload();
+        }
+        if let Some(cached) = Arc::make_mut(&mut self.tasks)
+            .iter_mut()
+            .find(|t| t.id == task.id)
+        {
+            *cached = task.clone();
+        }
+        self.revision += 1;
+        Ok(())
+    }
+
+    /// Whether completing the last open subtask completes the parent too.
+    pub fn auto_complete_parent(&self) -> bool {
+        self.store.auto_complete_parent().unwrap_or(true)
+    }
+
+    /// Add a step to a task, in the task's project.
+    pub fn add_subtask(&mut self, parent: &Task, description: String) -> Result<()> {
+        let mut task = Task::new(description);
+        task.parent_id = Some(parent.id.clone());
+        task.project_id = parent.project_id.clone();
+        self.add_task(task)
+    }
+
+    /// Move a task and its subtasks to the trash, stopping their timer.
+    pub fn delete_task(&mut self, id: &str) -> Result<()> {
+        let Some(task) = self.tasks.iter().find(|t| t.id == id).cloned() else {
+            return Ok(());
+        };
+        self.execute_all(delete_commands(&self.tasks, task))?;
+        // trashed tasks and their tracked time drop out of every view, and
+        // the store stopped their timer
+        self.reload()
+    }
+
+    /// Put the given tasks in this order, reusing the positions they hold
+    /// between them so tasks not in `ids`, e.g. filtered out ones, stay put.
+    pub fn reorder(&mut self, ids: &[String]) -> Result<()> {
+        let mut slots: Vec<i64> = self
+            .tasks
+            .iter()
+            .filter(|t| ids.contains(&t.id))
+            .map(|t| t.position)
+            .collect();
+        slots.sort_unstable();
+        slots.dedup();
+        // duplicate positions, e.g. from an import, get fresh ones at the end
+        let mut next = slots.last().copied().unwrap_or(0);
+        while slots.len() < ids.len() {
+            next += 1;
+            slots.push(next);
+        }
+        let positions: Vec<(String, i64)> = ids.iter().cloned().zip(slots).collect();
+
+        self.store.set_positions(&positions)?;
+        let tasks = Arc::make_mut(&mut self.tasks);
+        for task in tasks.iter_mut() {
+            if let Some((_, position)) = positions.iter().find(|(id, _)| *id == task.id) {
+                task.position = *position;
+            }
+        }
+        tasks.sort_by_key(|t| (t.position, t.created_at));
+        self.revision += 1;
+        Ok(())
+    }
+
+    /// Revert the last task change, returning what was undone.
+    pub fn undo(&mut self) -> Result<Option<Command>> {
+        let command = self.store.undo()?;
+        self.last_command = None;
+        self.reload()?;
+        Ok(command)
+    }
+
+    pub fn redo(&mut self) -> Result<Option<Command>> {
+        let command = self.store.redo()?;
+        self.last_command = None;
+        self.reload()?;
+        Ok(command)
+    }
+
+    pub fn restore_task(&mut self, id: &str) -> Result<()> {
+        self.store.restore_task(id)?;
+        self.reload()
+    }
+
+    /// Permanently delete a trashed task.
+    pub fn purge_task(&mut self, id: &str) -> Result<()> {
+        self.store.delete_task_by_id(id)?;
+        Arc::make_mut(&mut self.trash).retain(|t| t.id != id);
+        self.revision += 1;
+        Ok(())
+    }
+
+    pub fn empty_trash(&mut self) -> Result<()> {
+        self.store.empty_trash()?;
+        self.trash = Arc::default();
+        self.revision += 1;
+        Ok(())
+    }
+
+    pub fn find_or_create_project(&mut self, name: &str) -> Result<Project> {
+        let project = self.store.find_or_create_project(name)?;
+        let projects = Arc::make_mut(&mut self.projects);
+        match projects.iter_mut().find(|p| p.id == project.id) {
+            Some(cached) => *cached = project.clone(),
+            None => {
+                projects.push(project.clone());
+                projects.sort_by_key(|p| p.name.to_lowercase());
+            }
+        }
+        self.revision += 1;
+        Ok(project)
+    }
+
+    pub fn update_project(&mut self, project: &Project) -> Result<()> {
+        self.store.update_project(project)?;
+        let projects = Arc::make_mut(&mut self.projects);
+        if let Some(cached) = projects.iter_mut().find(|p| p.id == project.id) {
+            *cached = project.clone();
+        }
+        projects.sort_by_key(|p| p.name.to_lowercase());
+        self.revision += 1;
+        Ok(())
+    }
+
+    /// Whether descriptions are shown with their Markdown formatting rather
+    /// than as typed.
+    pub fn render_markdown(&self) -> bool {
+        self.render_markdown
+    }
+
+    pub fn set_render_markdown(&mut self, render: bool) -> Result<()> {
+        let value = if render { "true" } else { "false" };
+        self.set_setting(store::RENDER_MARKDOWN_SETTING, value)?;
+        self.render_markdown = render;
+        Ok(())
+    }
+
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        self.store.get_setting(key)
+    }
+
+    pub fn set_setting(&mut self, key: &str, value: &str) -> Result<()> {
+        self.store.set_setting(key, value)?;
+        self.revision += 1;
+        Ok(())
+    }
+}
+
+/// The parent of `task` before and after completing or reopening it along
+/// with `task`: completing the last open subtask completes the parent and
+/// reopening one reopens it.
+pub fn parent_update(tasks: &[Task], before: &Task, task: &Task) -> Option<(Task, Task)> {
+    if before.completed == task.completed {
+        return None;
+    }
+    let parent_id = task.parent_id.as_ref()?;
+    let parent = tasks.iter().find(|t| &t.id == parent_id)?;
+    let others_done = tasks
+        .iter()
+        .filter(|t| t.parent_id.as_ref() == Some(parent_id) && t.id != task.id)
+        .all(|t| t.completed);
+    let completes = task.completed && others_done && !parent.completed;
+    let reopens = !task.completed && parent.completed;
+    if !completes && !reopens {
+        return None;
+    }
+    let mut after = parent.clone();
+    after.set_completed(task.completed);
+    Some((parent.clone(), after))
+}
+
+/// Trash a task together with its subtasks.
+pub fn delete_commands(tasks: &[Task], task: Task) -> Vec<Command> {
+    let subtasks = tasks
+        .iter()
+        .filter(|t| t.parent_id.as_ref() == Some(&task.id))
+        .cloned()
+        .map(Command::delete)
+        .collect::<Vec<_>>();
+    let mut commands = vec![Command::delete(task)];
+    commands.extend(subtasks);
+    // restoring the task brings back the subtasks trashed at the same time
+    let deleted_at = commands[0].after.as_ref().and_then(|t| t.deleted_at);
+    for after in commands.iter_mut().filter_map(|c| c.after.as_mut()) {
+        after.deleted_at = deleted_at;
+    }
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types;
+
+    #[test]
+    fn picks_up_writes_from_another_connection() {
+        let dir = std::env::temp_dir().join(format!("small-worklog-{}", types::generate_uuid()));
+        let path = dir.join("worklog.db");
+        let mut worklog = Worklog::new(Store::open(&path).unwrap()).unwrap();
+        let other = Store::open(&path).unwrap();
+        assert!(!worklog.refresh_if_changed().unwrap());
+
+        // e.g. the CLI adding a task while the app is open
+        let task = Task::new("from the cli".to_owned());
+        other.execute(&Command::add(task.clone())).unwrap();
+        let revision = worklog.revision();
+        assert!(worklog.refresh_if_changed().unwrap());
+        assert_eq!(*worklog.tasks(), vec![task.clone()]);
+        assert!(worklog.revision() > revision);
+        assert!(!worklog.refresh_if_changed().unwrap());
+
+        // and undoing it there
+        other.undo().unwrap();
+        assert!(worklog.refresh_if_changed().unwrap());
+        assert!(worklog.tasks().is_empty());
+        assert_eq!(worklog.trash()[0].id, task.id);
+
+        drop((worklog, other));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}